@@ -0,0 +1,103 @@
+use odra::{Mapping, contract_env, execution_error, Event};
+use odra::types::Address;
+use odra::types::event::OdraEvent;
+
+pub type Role = [u8; 32];
+
+// administers every role, including itself
+pub const DEFAULT_ADMIN_ROLE: Role = [0u8; 32];
+// allowed to mint and burn tokens
+pub const MINTER_ROLE: Role = [1u8; 32];
+
+// roles held by accounts, each role is granted and revoked by the holders of its admin role
+#[odra::module(events = [RoleGranted, RoleRevoked])]
+pub struct AccessControl {
+    roles: Mapping<Role, Mapping<Address, bool>>,
+    role_admins: Mapping<Role, Role>
+}
+
+// codes start at 300 so they never collide with the Erc20 errors raised by the same contract
+execution_error! {
+    pub enum Error {
+        MissingRole => 300,
+        CanOnlyRenounceForSelf => 301,
+    }
+}
+
+#[odra::module]
+impl AccessControl {
+    pub fn has_role(&self, role: &Role, account: &Address) -> bool {
+        self.roles.get_instance(role).get_or_default(account)
+    }
+
+    // roles without an explicit admin are administered by DEFAULT_ADMIN_ROLE
+    pub fn get_role_admin(&self, role: &Role) -> Role {
+        self.role_admins.get_or_default(role)
+    }
+
+    pub fn grant_role(&mut self, role: &Role, account: &Address) {
+        self.check_role(&self.get_role_admin(role), &contract_env::caller());
+        self.unchecked_grant_role(role, account);
+    }
+
+    pub fn revoke_role(&mut self, role: &Role, account: &Address) {
+        self.check_role(&self.get_role_admin(role), &contract_env::caller());
+        self.unchecked_revoke_role(role, account);
+    }
+
+    // `account` has to be the caller, so a role is never dropped by mistake
+    pub fn renounce_role(&mut self, role: &Role, account: &Address) {
+        if *account != contract_env::caller() {
+            contract_env::revert(Error::CanOnlyRenounceForSelf)
+        }
+        self.unchecked_revoke_role(role, account);
+    }
+}
+
+impl AccessControl {
+    pub fn check_role(&self, role: &Role, account: &Address) {
+        if !self.has_role(role, account) {
+            contract_env::revert(Error::MissingRole)
+        }
+    }
+
+    pub fn unchecked_grant_role(&mut self, role: &Role, account: &Address) {
+        if self.has_role(role, account) {
+            return;
+        }
+        self.roles.get_instance(role).set(account, true);
+        RoleGranted {
+            role: *role,
+            account: *account,
+            sender: contract_env::caller()
+        }
+        .emit();
+    }
+
+    pub fn unchecked_revoke_role(&mut self, role: &Role, account: &Address) {
+        if !self.has_role(role, account) {
+            return;
+        }
+        self.roles.get_instance(role).set(account, false);
+        RoleRevoked {
+            role: *role,
+            account: *account,
+            sender: contract_env::caller()
+        }
+        .emit();
+    }
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct RoleGranted {
+    pub role: Role,
+    pub account: Address,
+    pub sender: Address
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct RoleRevoked {
+    pub role: Role,
+    pub account: Address,
+    pub sender: Address
+}
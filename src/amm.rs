@@ -1,132 +1,776 @@
-use odra::{Variable, contract_env, execution_error};
+use odra::{Variable, contract_env, execution_error, Event};
 use odra::types::{Address, Balance};
-use crate::erc20::{Erc20, Erc20Ref};
-use crate::math::{_sqrt, _min};
+use odra::types::event::OdraEvent;
+use crate::erc20::{Erc20, Erc20Ref, Transfer, Approval};
+use crate::oracle::{Oracle, Observation};
+use crate::flash::{FlashSwapCalleeRef, FlashLoanReceiverRef};
+use crate::reentrancy_guard::ReentrancyGuard;
+use crate::math::{_sqrt, _min, _quote, _uq112_div, _get_amount_out, _get_amount_in, FEE_DENOMINATOR};
 
-#[odra::module]
+#[odra::module(events = [Mint, Burn, Swap, FlashSwap, FlashLoan, Sync, Skim, Transfer, Approval])]
 pub struct AmmContract {
-    pub lq_token_address: Variable<Address>,
+    pub lq_token: Erc20,
     pub token0_address: Variable<Address>,
     pub token1_address: Variable<Address>,
     pub reserve0: Variable<Balance>,
-    pub reserve1: Variable<Balance>
+    pub reserve1: Variable<Balance>,
+    pub fee: Variable<u32>,
+    pub admin: Variable<Address>,
+    pub fee_to: Variable<Option<Address>>,
+    pub k_last: Variable<Balance>,
+    pub flash_loan_fee: Variable<u32>,
+    pub price0_cumulative_last: Variable<Balance>,
+    pub price1_cumulative_last: Variable<Balance>,
+    pub block_timestamp_last: Variable<u64>,
+    pub oracle: Oracle,
+    pub guard: ReentrancyGuard
 }
 
+// highest swap fee in basis points (10%)
+pub const MAX_FEE: u32 = 1000;
+// shares minted to the pool itself on the first deposit, the pool never spends them
+pub const MINIMUM_LIQUIDITY: u128 = 1000;
+// flash loan fee of new pools in basis points (0.09%)
+pub const DEFAULT_FLASH_LOAN_FEE: u32 = 9;
+
 execution_error! {
     pub enum Error{
-        InvalidContribution => 1
+        InvalidContribution => 1,
+        InsufficientOutputAmount => 2,
+        Expired => 3,
+        ExcessiveInputAmount => 4,
+        InsufficientLiquidity => 5,
+        InsufficientAmount0 => 6,
+        InsufficientAmount1 => 7,
+        InvalidToken => 8,
+        NotAdmin => 9,
+        FeeTooHigh => 10,
+        InsufficientInputAmount => 11,
+        InvalidTo => 12,
+        KInvariant => 13,
+        InsufficientRepayment => 14,
+        InsufficientInitialLiquidity => 15
     }
 }
 
 #[odra::module]
 impl AmmContract {
     #[odra(init)]
-    pub fn init(&mut self, lq_token_address: Address, token0_address: Address, token1_address: Address) {
-        self.lq_token_address.set(lq_token_address);
+    pub fn init(&mut self, token0_address: Address, token1_address: Address, fee: u32, admin: Address) {
+        if fee > MAX_FEE{
+            odra::contract_env::revert(Error::FeeTooHigh)
+        }
+        // the pool is its own LP token and the only one able to mint or burn it
+        self.lq_token.init_embedded("AMM LP".to_string(), "AMM-LP".to_string(), 18u8);
         self.token0_address.set(token0_address);
         self.token1_address.set(token1_address);
         self.reserve0.set(Balance::zero());
         self.reserve1.set(Balance::zero());
+        self.fee.set(fee);
+        self.admin.set(admin);
+        self.fee_to.set(None);
+        self.k_last.set(Balance::zero());
+        self.flash_loan_fee.set(DEFAULT_FLASH_LOAN_FEE);
+        self.price0_cumulative_last.set(Balance::zero());
+        self.price1_cumulative_last.set(Balance::zero());
+        self.block_timestamp_last.set(contract_env::get_block_time());
+        self.oracle.initialize(contract_env::get_block_time());
     }
-    pub fn add_liquidity(&mut self, amount0: Balance, amount1: Balance){
+    pub fn add_liquidity(&mut self, amount0_desired: Balance, amount1_desired: Balance, amount0_min: Balance, amount1_min: Balance) -> Balance{
+        self.guard.lock();
         let caller: Address = contract_env::caller();
-        // transfer approved tokens from caller to contract
-        Erc20Ref::at(&self.token0_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount0);
-        Erc20Ref::at(&self.token1_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount1);
-        
         // get reserves and total supply of LQ token
         let reserve0: &Balance = &self.reserve0.get().unwrap();
         let reserve1: &Balance = &self.reserve1.get().unwrap();
-        let totalSupply: Balance = Erc20Ref::at(&self.lq_token_address.get().unwrap()).total_supply();
-        // verify contribution
-        if reserve0 > &Balance::zero() || reserve1 > &Balance::zero(){
-            if (*reserve0 * amount1) != (*reserve1 * amount0){
-                odra::contract_env::revert(Error::InvalidContribution)
-            };
-        }
+        let fee_on: bool = self._mint_fee(*reserve0, *reserve1);
+        let totalSupply: Balance = self.lq_token.total_supply();
+        // calculate the optimal contribution for the current reserves
+        let (amount0, amount1) = self._optimal_amounts(amount0_desired, amount1_desired, amount0_min, amount1_min);
+        // transfer approved tokens from caller to contract, the rest stays with the caller
+        Erc20Ref::at(&self.token0_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount0);
+        Erc20Ref::at(&self.token1_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount1);
 
         // calculate the amount of shares to be minted
-        let mut shares: Balance = Balance::zero();
+        let shares: Balance = self._liquidity(amount0, amount1, totalSupply);
         if totalSupply == Balance::zero(){
-            shares = _sqrt(amount0 * amount1);
+            // the first MINIMUM_LIQUIDITY shares are locked in the pool forever
+            self.lq_token.raw_mint(&contract_env::self_address(), &Balance::from(MINIMUM_LIQUIDITY));
         }
-        else{
-            let a: Balance = amount0 * totalSupply / *reserve0;
-            let b: Balance = amount1 * totalSupply / *reserve1;
-            shares = _min(a, b);
-        }
-        Erc20Ref::at(&self.lq_token_address.get().unwrap()).mint(&caller, &shares);
+        self.lq_token.raw_mint(&caller, &shares);
 
-        // update reserve => move this to an internal function
-        let contract_balance_0 = Erc20Ref::at(&self.token0_address.get().unwrap()).balance_of(&contract_env::self_address());
-        let contract_balance_1 =Erc20Ref::at(&self.token1_address.get().unwrap()).balance_of(&contract_env::self_address());
-        self.reserve0.set(contract_balance_0);
-        self.reserve1.set(contract_balance_1);
+        // update reserves and emit events
+        self._update();
+        if fee_on{
+            self.k_last.set(self.reserve0.get().unwrap() * self.reserve1.get().unwrap());
+        }
+        Mint {
+            sender: caller,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        shares
     }
 
-    pub fn remove_liquidity(&mut self, shares: Balance){
+    pub fn remove_liquidity(&mut self, shares: Balance, amount0_min: Balance, amount1_min: Balance, deadline: u64) -> (Balance, Balance){
+        self.guard.lock();
+        self._ensure(deadline);
         let caller: Address = contract_env::caller();
-        // get balances and total supply of LQ token
-        let balance0: Balance = Erc20Ref::at(&self.token0_address.get().unwrap()).balance_of(&contract_env::self_address());
-        let balance1: Balance = Erc20Ref::at(&self.token1_address.get().unwrap()).balance_of(&contract_env::self_address());
-        let totalSupply: Balance = Erc20Ref::at(&self.lq_token_address.get().unwrap()).total_supply();
+        // get total supply of LQ token
+        let fee_on: bool = self._mint_fee(self.reserve0.get().unwrap(), self.reserve1.get().unwrap());
+        let totalSupply: Balance = self.lq_token.total_supply();
         // calculate output amounts
-        let amount0: Balance = shares * balance0 / totalSupply;
-        let amount1: Balance = shares * balance1 / totalSupply;
+        let (amount0, amount1) = self._burn_amounts(shares, totalSupply);
+        if amount0 < amount0_min{
+            odra::contract_env::revert(Error::InsufficientAmount0)
+        }
+        if amount1 < amount1_min{
+            odra::contract_env::revert(Error::InsufficientAmount1)
+        }
         // transfer output amounts and burn LQ token
-        Erc20Ref::at(&self.lq_token_address.get().unwrap()).burn(&caller, &shares);
+        self.lq_token.raw_burn(&caller, &shares);
         Erc20Ref::at(&self.token0_address.get().unwrap()).transfer(&caller, &amount0);
         Erc20Ref::at(&self.token1_address.get().unwrap()).transfer(&caller, &amount1);
-        // update reserve => move this to an internal function
-        let contract_balance_0: Balance = Erc20Ref::at(&self.token0_address.get().unwrap()).balance_of(&contract_env::self_address());
-        let contract_balance_1: Balance =Erc20Ref::at(&self.token1_address.get().unwrap()).balance_of(&contract_env::self_address());
-        self.reserve0.set(contract_balance_0);
-        self.reserve1.set(contract_balance_1);
+        // update reserves and emit events
+        self._update();
+        if fee_on{
+            self.k_last.set(self.reserve0.get().unwrap() * self.reserve1.get().unwrap());
+        }
+        Burn {
+            sender: caller,
+            amount0,
+            amount1,
+            to: caller
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
     }
     
-    pub fn swap(&mut self, amount: Balance, from_token_address: Address){
+    pub fn swap(&mut self, amount: Balance, from_token_address: Address) -> Balance{
+        self.guard.lock();
+        let amount_out: Balance = self._swap(amount, from_token_address, Balance::zero());
+        self.guard.unlock();
+        amount_out
+    }
+
+    pub fn swap_exact_input(&mut self, amount: Balance, from_token_address: Address, min_amount_out: Balance, deadline: u64) -> Balance{
+        self.guard.lock();
+        self._ensure(deadline);
+        let amount_out: Balance = self._swap(amount, from_token_address, min_amount_out);
+        self.guard.unlock();
+        amount_out
+    }
+
+    pub fn swap_for_exact_output(&mut self, amount_out: Balance, max_amount_in: Balance, to_token: Address) -> Balance{
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        // calculate required input amount with the pool fee, rounded up
+        let (tokenIn, amount_in) = self._amount_in(amount_out, to_token);
+        if amount_in > max_amount_in{
+            odra::contract_env::revert(Error::ExcessiveInputAmount)
+        }
+        // pull only the required input
+        Erc20Ref::at(&tokenIn).transfer_from(&caller, &contract_env::self_address(), &amount_in);
+        Erc20Ref::at(&to_token).transfer(&caller, &amount_out);
+        // update reserves and emit events
+        self._update();
+        Swap {
+            sender: caller,
+            amount_in,
+            amount_out,
+            token_in: tokenIn,
+            to: caller
+        }
+        .emit();
+        self.guard.unlock();
+        amount_in
+    }
+
+    // sends the outputs to `to` before payment, `to` must pay back through its callback
+    pub fn flash_swap(&mut self, amount0_out: Balance, amount1_out: Balance, to: Address, data: Vec<u8>){
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        if amount0_out == Balance::zero() && amount1_out == Balance::zero(){
+            odra::contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        if amount0_out >= reserve0 || amount1_out >= reserve1{
+            odra::contract_env::revert(Error::InsufficientLiquidity)
+        }
+        let token0_address: Address = self.token0_address.get().unwrap();
+        let token1_address: Address = self.token1_address.get().unwrap();
+        if to == token0_address || to == token1_address{
+            odra::contract_env::revert(Error::InvalidTo)
+        }
+        // optimistically transfer the outputs and call the receiver
+        if amount0_out > Balance::zero(){
+            Erc20Ref::at(&token0_address).transfer(&to, &amount0_out);
+        }
+        if amount1_out > Balance::zero(){
+            Erc20Ref::at(&token1_address).transfer(&to, &amount1_out);
+        }
+        FlashSwapCalleeRef::at(&to).on_flash_swap(caller, amount0_out, amount1_out, data);
+        // whatever exceeds the remaining reserves has been paid in
+        let balance0: Balance = Erc20Ref::at(&token0_address).balance_of(&contract_env::self_address());
+        let balance1: Balance = Erc20Ref::at(&token1_address).balance_of(&contract_env::self_address());
+        let amount0_in: Balance = if balance0 > reserve0 - amount0_out { balance0 - (reserve0 - amount0_out) } else { Balance::zero() };
+        let amount1_in: Balance = if balance1 > reserve1 - amount1_out { balance1 - (reserve1 - amount1_out) } else { Balance::zero() };
+        if amount0_in == Balance::zero() && amount1_in == Balance::zero(){
+            odra::contract_env::revert(Error::InsufficientInputAmount)
+        }
+        // the constant product of the balances net of the fee must not decrease
+        let fee: Balance = Balance::from(self.fee.get().unwrap());
+        let denominator: Balance = Balance::from(FEE_DENOMINATOR);
+        let balance0_adjusted: Balance = balance0 * denominator - amount0_in * fee;
+        let balance1_adjusted: Balance = balance1 * denominator - amount1_in * fee;
+        if balance0_adjusted * balance1_adjusted < reserve0 * reserve1 * denominator * denominator{
+            odra::contract_env::revert(Error::KInvariant)
+        }
+        // update reserves and emit events
+        self._update();
+        FlashSwap {
+            sender: caller,
+            amount0_in,
+            amount1_in,
+            amount0_out,
+            amount1_out,
+            to
+        }
+        .emit();
+        self.guard.unlock();
+    }
+
+    // lends `amount` of one of the pool tokens for the duration of the receiver callback
+    pub fn flash_loan(&mut self, receiver: Address, token: Address, amount: Balance, data: Vec<u8>){
+        self.guard.lock();
         let caller: Address = contract_env::caller();
+        let reserve: Balance = self.max_flash_loan(token);
+        if amount > reserve{
+            odra::contract_env::revert(Error::InsufficientLiquidity)
+        }
+        let fee: Balance = self.flash_fee(token, amount);
+        let balance_before: Balance = Erc20Ref::at(&token).balance_of(&contract_env::self_address());
+        Erc20Ref::at(&token).transfer(&receiver, &amount);
+        FlashLoanReceiverRef::at(&receiver).on_flash_loan(caller, token, amount, fee, data);
+        // the receiver pays back the loan and the fee with a transfer
+        let balance_after: Balance = Erc20Ref::at(&token).balance_of(&contract_env::self_address());
+        if balance_after < balance_before + fee{
+            odra::contract_env::revert(Error::InsufficientRepayment)
+        }
+        // the fee is added to the reserves and accrues to the LPs
+        self._update();
+        FlashLoan {
+            initiator: caller,
+            receiver,
+            token,
+            amount,
+            fee
+        }
+        .emit();
+        self.guard.unlock();
+    }
+
+    // forces the reserves to match the token balances of the pool
+    pub fn sync(&mut self){
+        self.guard.lock();
+        self._update();
+        self.guard.unlock();
+    }
+
+    // sends the token balances in excess of the reserves to `to`
+    pub fn skim(&mut self, to: Address){
+        self.guard.lock();
+        let token0_address: Address = self.token0_address.get().unwrap();
+        let token1_address: Address = self.token1_address.get().unwrap();
+        let amount0: Balance = Erc20Ref::at(&token0_address).balance_of(&contract_env::self_address()).saturating_sub(self.reserve0.get().unwrap());
+        let amount1: Balance = Erc20Ref::at(&token1_address).balance_of(&contract_env::self_address()).saturating_sub(self.reserve1.get().unwrap());
+        if amount0 > Balance::zero(){
+            Erc20Ref::at(&token0_address).transfer(&to, &amount0);
+        }
+        if amount1 > Balance::zero(){
+            Erc20Ref::at(&token1_address).transfer(&to, &amount1);
+        }
+        Skim {
+            to,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+    }
+
+    pub fn max_flash_loan(&self, token: Address) -> Balance{
+        if token == self.token0_address.get().unwrap(){
+            return self.reserve0.get().unwrap();
+        }
+        if token == self.token1_address.get().unwrap(){
+            return self.reserve1.get().unwrap();
+        }
+        odra::contract_env::revert(Error::InvalidToken)
+    }
+
+    // flash loan fee for `amount`, rounded up in favour of the pool
+    pub fn flash_fee(&self, token: Address, amount: Balance) -> Balance{
+        self.max_flash_loan(token);
+        let numerator: Balance = amount * Balance::from(self.flash_loan_fee.get_or_default());
+        (numerator + Balance::from(FEE_DENOMINATOR - 1)) / Balance::from(FEE_DENOMINATOR)
+    }
+
+    pub fn flash_loan_fee(&self) -> u32{
+        self.flash_loan_fee.get_or_default()
+    }
+
+    pub fn set_flash_loan_fee(&mut self, fee: u32){
+        self._only_admin();
+        if fee > MAX_FEE{
+            odra::contract_env::revert(Error::FeeTooHigh)
+        }
+        self.flash_loan_fee.set(fee);
+    }
+
+    pub fn reserve0(&self) -> Balance{
+        *&self.reserve0.get().unwrap()
+    }
+
+    pub fn reserve1(&self) -> Balance{
+        *&self.reserve1.get().unwrap()
+    }
+
+    pub fn fee(&self) -> u32{
+        self.fee.get().unwrap()
+    }
+
+    pub fn set_fee(&mut self, fee: u32){
+        self._only_admin();
+        if fee > MAX_FEE{
+            odra::contract_env::revert(Error::FeeTooHigh)
+        }
+        self.fee.set(fee);
+    }
+
+    pub fn admin(&self) -> Address{
+        self.admin.get().unwrap()
+    }
+
+    pub fn fee_to(&self) -> Option<Address>{
+        self.fee_to.get_or_default()
+    }
+
+    // the protocol fee is switched off by setting `fee_to` to None
+    pub fn set_fee_to(&mut self, fee_to: Option<Address>){
+        self._only_admin();
+        self.fee_to.set(fee_to);
+    }
+
+    pub fn k_last(&self) -> Balance{
+        self.k_last.get_or_default()
+    }
+
+    pub fn price0_cumulative_last(&self) -> Balance{
+        self.price0_cumulative_last.get_or_default()
+    }
+
+    pub fn price1_cumulative_last(&self) -> Balance{
+        self.price1_cumulative_last.get_or_default()
+    }
+
+    pub fn block_timestamp_last(&self) -> u64{
+        self.block_timestamp_last.get_or_default()
+    }
+
+    pub fn increase_observation_cardinality(&mut self, observation_cardinality_next: u32){
+        self.oracle.grow(observation_cardinality_next);
+    }
+
+    // cumulative prices and liquidity `seconds_ago` time units before the current block
+    pub fn observe(&self, seconds_ago: Vec<u64>) -> Vec<Observation>{
+        let now: u64 = contract_env::get_block_time();
+        self.oracle.observe(now, seconds_ago, self._current_observation(now))
+    }
+
+    pub fn observation(&self, index: u32) -> Option<Observation>{
+        self.oracle.observation(index)
+    }
+
+    pub fn observation_index(&self) -> u32{
+        self.oracle.index()
+    }
+
+    pub fn observation_cardinality(&self) -> u32{
+        self.oracle.cardinality()
+    }
+
+    pub fn observation_cardinality_next(&self) -> u32{
+        self.oracle.cardinality_next()
+    }
+
+    pub fn token0(&self) -> Address{
+        self.token0_address.get().unwrap()
+    }
+
+    pub fn token1(&self) -> Address{
+        self.token1_address.get().unwrap()
+    }
+
+    pub fn lq_token(&self) -> Address{
+        contract_env::self_address()
+    }
+
+    pub fn get_amount_out(&self, amount_in: Balance, token_in: Address) -> Balance{
+        let (_, amount_out) = self._amount_out(amount_in, token_in);
+        amount_out
+    }
+
+    pub fn get_amount_in(&self, amount_out: Balance, token_out: Address) -> Balance{
+        let (_, amount_in) = self._amount_in(amount_out, token_out);
+        amount_in
+    }
+
+    pub fn quote(&self, amount_a: Balance, reserve_a: Balance, reserve_b: Balance) -> Balance{
+        if reserve_a == Balance::zero() || reserve_b == Balance::zero(){
+            odra::contract_env::revert(Error::InsufficientLiquidity)
+        }
+        _quote(amount_a, reserve_a, reserve_b)
+    }
+
+    // shares `add_liquidity` would mint for the desired amounts, including the pending protocol fee
+    pub fn quote_add_liquidity(&self, amount0: Balance, amount1: Balance) -> Balance{
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let totalSupply: Balance = self.lq_token.total_supply() + self._fee_liquidity(reserve0, reserve1);
+        let (amount0, amount1) = self._optimal_amounts(amount0, amount1, Balance::zero(), Balance::zero());
+        self._liquidity(amount0, amount1, totalSupply)
+    }
+
+    // token amounts `remove_liquidity` would pay out for `shares`
+    pub fn quote_remove_liquidity(&self, shares: Balance) -> (Balance, Balance){
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let totalSupply: Balance = self.lq_token.total_supply() + self._fee_liquidity(reserve0, reserve1);
+        self._burn_amounts(shares, totalSupply)
+    }
+
+    // LP token entry points, minting and burning stay internal to the pool
+    pub fn name(&self) -> String{
+        self.lq_token.name()
+    }
+
+    pub fn symbol(&self) -> String{
+        self.lq_token.symbol()
+    }
+
+    pub fn decimals(&self) -> u8{
+        self.lq_token.decimals()
+    }
+
+    pub fn total_supply(&self) -> Balance{
+        self.lq_token.total_supply()
+    }
+
+    pub fn balance_of(&self, address: &Address) -> Balance{
+        self.lq_token.balance_of(address)
+    }
+
+    pub fn allowance(&self, owner: &Address, spender: &Address) -> Balance{
+        self.lq_token.allowance(owner, spender)
+    }
+
+    pub fn transfer(&mut self, recipient: &Address, amount: &Balance){
+        self.lq_token.transfer(recipient, amount);
+    }
+
+    pub fn transfer_from(&mut self, owner: &Address, recipient: &Address, amount: &Balance){
+        self.lq_token.transfer_from(owner, recipient, amount);
+    }
+
+    pub fn approve(&mut self, spender: &Address, amount: &Balance){
+        self.lq_token.approve(spender, amount);
+    }
+
+    fn _swap(&mut self, amount: Balance, from_token_address: Address, min_amount_out: Balance) -> Balance{
+        let caller: Address = contract_env::caller();
+        // calculate output amount with the pool fee
+        let (tokenOut, amountOut) = self._amount_out(amount, from_token_address);
+        if amountOut < min_amount_out{
+            odra::contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        // transfer tokens to contract
+        Erc20Ref::at(&from_token_address).transfer_from(&caller, &contract_env::self_address(), &amount);
+        Erc20Ref::at(&tokenOut).transfer(&caller, &amountOut);
+        // update reserves and emit events
+        self._update();
+        Swap {
+            sender: caller,
+            amount_in: amount,
+            amount_out: amountOut,
+            token_in: from_token_address,
+            to: caller
+        }
+        .emit();
+        amountOut
+    }
+
+    // output token and output amount of a swap from `token_in`
+    fn _amount_out(&self, amount_in: Balance, token_in: Address) -> (Address, Balance){
+        let (token_out, reserve_in, reserve_out) = self._route(token_in);
+        (token_out, _get_amount_out(amount_in, reserve_in, reserve_out, self.fee.get().unwrap()))
+    }
+
+    // input token and input amount of a swap to `token_out`
+    fn _amount_in(&self, amount_out: Balance, token_out: Address) -> (Address, Balance){
+        let token_in: Address = self._other_token(token_out);
+        let (_, reserve_in, reserve_out) = self._route(token_in);
+        if amount_out >= reserve_out{
+            odra::contract_env::revert(Error::InsufficientLiquidity)
+        }
+        (token_in, _get_amount_in(amount_out, reserve_in, reserve_out, self.fee.get().unwrap()))
+    }
+
+    // output token, input reserve and output reserve of a swap from `token_in`
+    // priced from the stored reserves like flash swaps, so unsynced balances never move the price
+    fn _route(&self, token_in: Address) -> (Address, Balance, Balance){
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let token_out: Address = self._other_token(token_in);
+        if token_in == self.token0_address.get().unwrap(){
+            return (token_out, reserve0, reserve1);
+        }
+        (token_out, reserve1, reserve0)
+    }
+
+    fn _other_token(&self, token: Address) -> Address{
+        let token0_address: Address = self.token0_address.get().unwrap();
+        let token1_address: Address = self.token1_address.get().unwrap();
+        if token == token0_address{
+            return token1_address;
+        }
+        if token != token1_address{
+            odra::contract_env::revert(Error::InvalidToken)
+        }
+        token0_address
+    }
+
+    fn _balances(&self) -> (Balance, Balance){
         let balance0: Balance = Erc20Ref::at(&self.token0_address.get().unwrap()).balance_of(&contract_env::self_address());
         let balance1: Balance = Erc20Ref::at(&self.token1_address.get().unwrap()).balance_of(&contract_env::self_address());
-        let token0_address: &Address = &self.token0_address.get().unwrap();
-        let token1_address: &Address = &self.token1_address.get().unwrap();
-        let mut tokenIn: &Address = token0_address;
-        let mut tokenOut: &Address = token1_address;
-        let mut reserveIn: Balance = balance0;
-        let mut reserveOut: Balance = balance1;
-        if &from_token_address == token1_address{
-            tokenIn = token1_address;
-            tokenOut = token0_address;
-            reserveIn = balance1;
-            reserveOut = balance0;
+        (balance0, balance1)
+    }
+
+    // shares minted for a contribution at the current reserves
+    fn _liquidity(&self, amount0: Balance, amount1: Balance, total_supply: Balance) -> Balance{
+        let mut shares: Balance = Balance::zero();
+        if total_supply == Balance::zero(){
+            shares = _sqrt(amount0 * amount1);
+            if shares <= Balance::from(MINIMUM_LIQUIDITY){
+                odra::contract_env::revert(Error::InsufficientInitialLiquidity)
+            }
+            shares = shares - Balance::from(MINIMUM_LIQUIDITY);
         }
-        // transfer tokens to contract
-        Erc20Ref::at(tokenIn).transfer(&contract_env::caller(), &amount);
-        // calculate output amount with 0.3% fee
-        let amountInWithFee: Balance = (amount * Balance::from(997)) / Balance::from(1000);
-        let amountOut: Balance = (reserveOut * amountInWithFee) / (reserveIn + amountInWithFee);
-        Erc20Ref::at(tokenOut).transfer(&caller, &amountOut);
-        // update reserve => move this to an internal function
+        else{
+            let a: Balance = amount0 * total_supply / self.reserve0.get().unwrap();
+            let b: Balance = amount1 * total_supply / self.reserve1.get().unwrap();
+            shares = _min(a, b);
+        }
+        if shares == Balance::zero(){
+            odra::contract_env::revert(Error::InvalidContribution)
+        }
+        shares
+    }
+
+    // token amounts paid out for burning `shares`
+    fn _burn_amounts(&self, shares: Balance, total_supply: Balance) -> (Balance, Balance){
+        if total_supply == Balance::zero(){
+            odra::contract_env::revert(Error::InsufficientLiquidity)
+        }
+        let (balance0, balance1) = self._balances();
+        (shares * balance0 / total_supply, shares * balance1 / total_supply)
+    }
+
+    fn _optimal_amounts(&self, amount0_desired: Balance, amount1_desired: Balance, amount0_min: Balance, amount1_min: Balance) -> (Balance, Balance){
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        // the first contribution sets the price
+        if reserve0 == Balance::zero() && reserve1 == Balance::zero(){
+            return (amount0_desired, amount1_desired);
+        }
+        let amount1_optimal: Balance = _quote(amount0_desired, reserve0, reserve1);
+        if amount1_optimal <= amount1_desired{
+            if amount1_optimal < amount1_min{
+                odra::contract_env::revert(Error::InsufficientAmount1)
+            }
+            return (amount0_desired, amount1_optimal);
+        }
+        let amount0_optimal: Balance = _quote(amount1_desired, reserve1, reserve0);
+        if amount0_optimal < amount0_min{
+            odra::contract_env::revert(Error::InsufficientAmount0)
+        }
+        (amount0_optimal, amount1_desired)
+    }
+
+    // sets the reserves to the current token balances of the pool
+    // and accumulates the prices of the previous reserves
+    fn _update(&mut self){
         let contract_balance_0: Balance = Erc20Ref::at(&self.token0_address.get().unwrap()).balance_of(&contract_env::self_address());
         let contract_balance_1: Balance = Erc20Ref::at(&self.token1_address.get().unwrap()).balance_of(&contract_env::self_address());
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let block_timestamp: u64 = contract_env::get_block_time();
+        let time_elapsed: u64 = block_timestamp - self.block_timestamp_last.get_or_default();
+        if time_elapsed > 0 && reserve0 != Balance::zero() && reserve1 != Balance::zero(){
+            // prices are UQ112x112 fixed point numbers
+            self.price0_cumulative_last.add(_uq112_div(reserve1, reserve0) * Balance::from(time_elapsed));
+            self.price1_cumulative_last.add(_uq112_div(reserve0, reserve1) * Balance::from(time_elapsed));
+        }
+        if time_elapsed > 0{
+            self.oracle.write(block_timestamp, self.price0_cumulative_last.get_or_default(), self.price1_cumulative_last.get_or_default(), _sqrt(reserve0 * reserve1));
+        }
+        self.block_timestamp_last.set(block_timestamp);
         self.reserve0.set(contract_balance_0);
         self.reserve1.set(contract_balance_1);
+        Sync {
+            reserve0: contract_balance_0,
+            reserve1: contract_balance_1
+        }
+        .emit();
     }
 
-    pub fn reserve0(&self) -> Balance{
-        *&self.reserve0.get().unwrap()
+    // the observation the next reserve update at `now` would write
+    fn _current_observation(&self, now: u64) -> Observation{
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let newest: Observation = self.oracle.observation(self.oracle.index()).unwrap();
+        let time_elapsed: Balance = Balance::from(now - self.block_timestamp_last.get_or_default());
+        let mut price0_cumulative: Balance = self.price0_cumulative_last.get_or_default();
+        let mut price1_cumulative: Balance = self.price1_cumulative_last.get_or_default();
+        if reserve0 != Balance::zero() && reserve1 != Balance::zero(){
+            price0_cumulative = price0_cumulative + _uq112_div(reserve1, reserve0) * time_elapsed;
+            price1_cumulative = price1_cumulative + _uq112_div(reserve0, reserve1) * time_elapsed;
+        }
+        Observation {
+            timestamp: now,
+            price0_cumulative,
+            price1_cumulative,
+            liquidity_cumulative: newest.liquidity_cumulative + _sqrt(reserve0 * reserve1) * Balance::from(now - newest.timestamp),
+            initialized: true
+        }
     }
 
-    pub fn reserve1(&self) -> Balance{
-        *&self.reserve1.get().unwrap()
+    // mints 1/6th of the growth in sqrt(k) to `fee_to` if the protocol fee is on
+    fn _mint_fee(&mut self, reserve0: Balance, reserve1: Balance) -> bool{
+        match self.fee_to.get_or_default(){
+            Some(fee_to) => {
+                let liquidity: Balance = self._fee_liquidity(reserve0, reserve1);
+                if liquidity > Balance::zero(){
+                    self.lq_token.raw_mint(&fee_to, &liquidity);
+                }
+                true
+            }
+            None => {
+                if self.k_last.get_or_default() != Balance::zero(){
+                    self.k_last.set(Balance::zero());
+                }
+                false
+            }
+        }
+    }
+
+    // shares the next `_mint_fee` mints to `fee_to`
+    fn _fee_liquidity(&self, reserve0: Balance, reserve1: Balance) -> Balance{
+        let k_last: Balance = self.k_last.get_or_default();
+        if self.fee_to.get_or_default().is_none() || k_last == Balance::zero(){
+            return Balance::zero();
+        }
+        let root_k: Balance = _sqrt(reserve0 * reserve1);
+        let root_k_last: Balance = _sqrt(k_last);
+        if root_k <= root_k_last{
+            return Balance::zero();
+        }
+        let totalSupply: Balance = self.lq_token.total_supply();
+        let numerator: Balance = totalSupply * (root_k - root_k_last);
+        let denominator: Balance = root_k * Balance::from(5) + root_k_last;
+        numerator / denominator
     }
+
+    fn _only_admin(&self){
+        if contract_env::caller() != self.admin.get().unwrap(){
+            odra::contract_env::revert(Error::NotAdmin)
+        }
+    }
+
+    fn _ensure(&self, deadline: u64){
+        if contract_env::get_block_time() > deadline{
+            odra::contract_env::revert(Error::Expired)
+        }
+    }
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Mint {
+    pub sender: Address,
+    pub amount0: Balance,
+    pub amount1: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Burn {
+    pub sender: Address,
+    pub amount0: Balance,
+    pub amount1: Balance,
+    pub to: Address
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Swap {
+    pub sender: Address,
+    pub amount_in: Balance,
+    pub amount_out: Balance,
+    pub token_in: Address,
+    pub to: Address
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct FlashSwap {
+    pub sender: Address,
+    pub amount0_in: Balance,
+    pub amount1_in: Balance,
+    pub amount0_out: Balance,
+    pub amount1_out: Balance,
+    pub to: Address
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct FlashLoan {
+    pub initiator: Address,
+    pub receiver: Address,
+    pub token: Address,
+    pub amount: Balance,
+    pub fee: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Sync {
+    pub reserve0: Balance,
+    pub reserve1: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Skim {
+    pub to: Address,
+    pub amount0: Balance,
+    pub amount1: Balance
 }
 
 #[cfg(test)]
 mod tests {
-    use odra::types::{Address, Balance};
-    use crate::erc20::{Erc20, Erc20Ref, Erc20Deployer};
-    use super::{AmmContractDeployer, AmmContractRef};
+    use odra::{assert_events, contract_env, test_env, Variable};
+    use odra::types::{Address, Balance, OdraError, VmError};
+    use crate::erc20::{Erc20, Erc20Ref, Erc20Deployer, Transfer};
+    use super::{AmmContractDeployer, AmmContractRef, Error, Mint, Burn, Swap, FlashSwap, FlashLoan, Sync, Skim, MAX_FEE, MINIMUM_LIQUIDITY};
+    use crate::oracle::{Observation, Error as OracleError, MAX_CARDINALITY};
+    use crate::reentrancy_guard::Error as GuardError;
     #[test]
     fn test_erc20(){
         let user: Address = odra::test_env::get_account(1);
@@ -139,56 +783,103 @@ mod tests {
     #[test]
     fn add_Liquidity() {
         let user: Address = odra::test_env::get_account(1);
-        let lq_token_address: Address = Erc20Deployer::init("TOKEN".to_string(), "TKN".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
         let token0_address: Address = Erc20Deployer::init("TOKEN0".to_string(), "TKN0".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
         let token1_address: Address = Erc20Deployer::init("TOKEN1".to_string(), "TKN1".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
-        let amm_contract: Address = AmmContractDeployer::init(lq_token_address, token0_address, token1_address).address().to_owned();
+        let amm_contract: Address = AmmContractDeployer::init(token0_address, token1_address, 30, odra::test_env::get_account(0)).address().to_owned();
         // fund user with token0 and token1
-        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
-        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(1000u128));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(2000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(2000u128));
         change_caller(user);
         // approve contract as spender
-        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
-        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(1000u128));
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(2000u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(2000u128));
         // add liquidity
-        AmmContractRef::at(&amm_contract).add_liquidity(Balance::from(1000u128), Balance::from(1000u128));
+        AmmContractRef::at(&amm_contract).add_liquidity(Balance::from(2000u128), Balance::from(2000u128), Balance::zero(), Balance::zero());
         // verify reserve balance
         let reserve0: Balance = AmmContractRef::at(&amm_contract).reserve0();
         let reserve1: Balance = AmmContractRef::at(&amm_contract).reserve1();
 
         assert_eq!(reserve0, reserve1);
-        assert_eq!(reserve0, Balance::from(1000u128));
+        assert_eq!(reserve0, Balance::from(2000u128));
+    }
+    #[test]
+    fn minimum_liquidity(){
+        let (user, _, _, amm_contract) = setup(Balance::from(5000u128));
+        // the first MINIMUM_LIQUIDITY shares belong to the pool
+        assert_eq!(Erc20Ref::at(&amm_contract).balance_of(&amm_contract), Balance::from(MINIMUM_LIQUIDITY));
+        assert_eq!(Erc20Ref::at(&amm_contract).balance_of(&user), Balance::from(5000u128 - MINIMUM_LIQUIDITY));
+        assert_eq!(Erc20Ref::at(&amm_contract).total_supply(), Balance::from(5000u128));
+    }
+    #[test]
+    fn lq_token_supply_is_pool_only(){
+        let (user, _, _, amm_contract) = setup(Balance::from(5000u128));
+        let outsider: Address = odra::test_env::get_account(2);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        assert_eq!(amm_contract, amm.lq_token());
+        assert_eq!(amm.symbol(), "AMM-LP".to_string());
+        // shares move like any Erc20
+        change_caller(user);
+        amm.transfer(&outsider, &Balance::from(1000u128));
+        assert_eq!(amm.balance_of(&outsider), Balance::from(1000u128));
+        // nobody, not even the admin, can mint or burn shares directly
+        for caller in [outsider, user, odra::test_env::get_account(0)]{
+            change_caller(caller);
+            test_env::assert_exception(OdraError::VmError(VmError::NoSuchMethod("mint".to_string())), || {
+                Erc20Ref::at(&amm_contract).mint(&caller, &Balance::from(1000u128));
+            });
+            test_env::assert_exception(OdraError::VmError(VmError::NoSuchMethod("burn".to_string())), || {
+                Erc20Ref::at(&amm_contract).burn(&user, &Balance::from(1000u128));
+            });
+        }
+        assert_eq!(amm.total_supply(), Balance::from(5000u128));
+        assert_eq!(amm.balance_of(&user), Balance::from(3000u128));
+    }
+    #[test]
+    fn insufficient_initial_liquidity(){
+        let user: Address = odra::test_env::get_account(1);
+        let token0_address: Address = Erc20Deployer::init("TOKEN0".to_string(), "TKN0".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
+        let token1_address: Address = Erc20Deployer::init("TOKEN1".to_string(), "TKN1".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
+        let amm_contract: Address = AmmContractDeployer::init(token0_address, token1_address, 30, odra::test_env::get_account(0)).address().to_owned();
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(1000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        // sqrt(1000 * 1000) shares would all be locked
+        test_env::assert_exception(Error::InsufficientInitialLiquidity, || {
+            amm.add_liquidity(Balance::from(1000u128), Balance::from(1000u128), Balance::zero(), Balance::zero());
+        });
     }
     #[test]
     fn remove_Liquidity(){
         let user: Address = odra::test_env::get_account(1);
-        let lq_token_address: Address = Erc20Deployer::init("TOKEN".to_string(), "TKN".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
         let token0_address: Address = Erc20Deployer::init("TOKEN0".to_string(), "TKN0".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
         let token1_address: Address = Erc20Deployer::init("TOKEN1".to_string(), "TKN1".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
-        let amm_contract: Address = AmmContractDeployer::init(lq_token_address, token0_address, token1_address).address().to_owned();
+        let amm_contract: Address = AmmContractDeployer::init(token0_address, token1_address, 30, odra::test_env::get_account(0)).address().to_owned();
         { /* ADD LIQUIDITY */
             // fund user with token0 and token1
-            Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
-            Erc20Ref::at(&token1_address).mint(&user, &Balance::from(1000u128));
+            Erc20Ref::at(&token0_address).mint(&user, &Balance::from(2000u128));
+            Erc20Ref::at(&token1_address).mint(&user, &Balance::from(2000u128));
             change_caller(user);
             // approve contract as spender
-            Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
-            Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(1000u128));
+            Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(2000u128));
+            Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(2000u128));
             // add liquidity
-            AmmContractRef::at(&amm_contract).add_liquidity(Balance::from(1000u128), Balance::from(1000u128));
+            AmmContractRef::at(&amm_contract).add_liquidity(Balance::from(2000u128), Balance::from(2000u128), Balance::zero(), Balance::zero());
             // verify reserve balance
             let reserve0: Balance = AmmContractRef::at(&amm_contract).reserve0();
             let reserve1: Balance = AmmContractRef::at(&amm_contract).reserve1();
     
             assert_eq!(reserve0, reserve1);
-            assert_eq!(reserve0, Balance::from(1000u128));
+            assert_eq!(reserve0, Balance::from(2000u128));
         };
         // get shares
-        let shares: Balance = Erc20Ref::at(&lq_token_address).balance_of(&user);
+        let shares: Balance = AmmContractRef::at(&amm_contract).balance_of(&user);
         assert_eq!(shares, Balance::from(1000));
         // remove liquidity
         change_caller(user);
-        AmmContractRef::at(&amm_contract).remove_liquidity(shares);
+        AmmContractRef::at(&amm_contract).remove_liquidity(shares, Balance::from(1000u128), Balance::from(1000u128), u64::MAX);
         // check redeemed balance
         let user_balance0: Balance = Erc20Ref::at(&token0_address).balance_of(&user);
         let user_balance1: Balance = Erc20Ref::at(&token1_address).balance_of(&user);
@@ -199,10 +890,9 @@ mod tests {
     #[test]
     fn swap(){
         let user: Address = odra::test_env::get_account(1);
-        let lq_token_address: Address = Erc20Deployer::init("TOKEN".to_string(), "TKN".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
         let token0_address: Address = Erc20Deployer::init("TOKEN0".to_string(), "TKN0".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
         let token1_address: Address = Erc20Deployer::init("TOKEN1".to_string(), "TKN1".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
-        let amm_contract: Address = AmmContractDeployer::init(lq_token_address, token0_address, token1_address).address().to_owned();
+        let amm_contract: Address = AmmContractDeployer::init(token0_address, token1_address, 30, odra::test_env::get_account(0)).address().to_owned();
         { /* ADD LIQUIDITY */
             // fund user with token0 and token1
             Erc20Ref::at(&token0_address).mint(&user, &Balance::from(5000u128));
@@ -212,7 +902,7 @@ mod tests {
             Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(5000u128));
             Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(5000u128));
             // add liquidity
-            AmmContractRef::at(&amm_contract).add_liquidity(Balance::from(5000u128), Balance::from(5000u128));
+            AmmContractRef::at(&amm_contract).add_liquidity(Balance::from(5000u128), Balance::from(5000u128), Balance::zero(), Balance::zero());
             // verify reserve balance
             let reserve0: Balance = AmmContractRef::at(&amm_contract).reserve0();
             let reserve1: Balance = AmmContractRef::at(&amm_contract).reserve1();
@@ -223,6 +913,7 @@ mod tests {
             assert_eq!(Balance::from(0u128), Erc20Ref::at(&token1_address).balance_of(&user));
         };
         // perform a swap
+        change_caller(odra::test_env::get_account(0));
         Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
         // approve the contract to spend user's token0
         change_caller(user);
@@ -231,7 +922,720 @@ mod tests {
         AmmContractRef::at(&amm_contract).swap(Balance::from(1000u128), token0_address);
         // check balances
         assert_eq!(Balance::from(831u128), Erc20Ref::at(&token1_address).balance_of(&user));
+        assert_eq!(Balance::from(0u128), Erc20Ref::at(&token0_address).balance_of(&user));
+        // the pool received the input and paid out the output
+        assert_eq!(Balance::from(6000u128), Erc20Ref::at(&token0_address).balance_of(&amm_contract));
+        assert_eq!(Balance::from(4169u128), Erc20Ref::at(&token1_address).balance_of(&amm_contract));
+        assert_eq!(Balance::from(6000u128), AmmContractRef::at(&amm_contract).reserve0());
+        assert_eq!(Balance::from(4169u128), AmmContractRef::at(&amm_contract).reserve1());
+
+    }
+    #[test]
+    fn swap_token1_for_token0(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(1000u128));
+        AmmContractRef::at(&amm_contract).swap(Balance::from(1000u128), token1_address);
+        assert_eq!(Balance::from(831u128), Erc20Ref::at(&token0_address).balance_of(&user));
+        assert_eq!(Balance::from(0u128), Erc20Ref::at(&token1_address).balance_of(&user));
+        assert_eq!(Balance::from(4169u128), Erc20Ref::at(&token0_address).balance_of(&amm_contract));
+        assert_eq!(Balance::from(6000u128), Erc20Ref::at(&token1_address).balance_of(&amm_contract));
+    }
+    #[test]
+    fn swap_invalid_token(){
+        let (user, _, _, amm_contract) = setup(Balance::from(5000u128));
+        change_caller(user);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        test_env::assert_exception(Error::InvalidToken, || {
+            amm.swap(Balance::from(1000u128), amm_contract);
+        });
+        test_env::assert_exception(Error::InvalidToken, || {
+            amm.swap_for_exact_output(Balance::from(100u128), Balance::from(1000u128), amm_contract);
+        });
+    }
+    #[test]
+    fn swap_exact_input(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        // swap token0 for token1 within the bounds
+        let amount_out: Balance = AmmContractRef::at(&amm_contract).swap_exact_input(Balance::from(1000u128), token0_address, Balance::from(831u128), u64::MAX);
+        assert_eq!(amount_out, Balance::from(831u128));
+        assert_eq!(Balance::from(831u128), Erc20Ref::at(&token1_address).balance_of(&user));
+    }
+    #[test]
+    fn swap_exact_input_errors(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        // the output would be 831
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            amm.swap_exact_input(Balance::from(1000u128), token0_address, Balance::from(832u128), u64::MAX);
+        });
+        // the transaction is included after the deadline
+        test_env::advance_block_time_by(100);
+        test_env::assert_exception(Error::Expired, || {
+            amm.swap_exact_input(Balance::from(1000u128), token0_address, Balance::zero(), 99);
+        });
+    }
+    #[test]
+    fn swap_for_exact_output(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(2000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(2000u128));
+        // buy exactly 831 token1, which costs 1000 token0
+        let amount_in: Balance = AmmContractRef::at(&amm_contract).swap_for_exact_output(Balance::from(831u128), Balance::from(2000u128), token1_address);
+        assert_eq!(amount_in, Balance::from(1000u128));
+        assert_eq!(Balance::from(831u128), Erc20Ref::at(&token1_address).balance_of(&user));
+        assert_eq!(Balance::from(1000u128), Erc20Ref::at(&token0_address).balance_of(&user));
+        assert_eq!(Balance::from(6000u128), AmmContractRef::at(&amm_contract).reserve0());
+        assert_eq!(Balance::from(4169u128), AmmContractRef::at(&amm_contract).reserve1());
+    }
+    #[test]
+    fn swap_for_exact_output_errors(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(2000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(2000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        // 831 token1 cost 1000 token0
+        test_env::assert_exception(Error::ExcessiveInputAmount, || {
+            amm.swap_for_exact_output(Balance::from(831u128), Balance::from(999u128), token1_address);
+        });
+        // the pool cannot be drained
+        test_env::assert_exception(Error::InsufficientLiquidity, || {
+            amm.swap_for_exact_output(Balance::from(5000u128), Balance::from(2000u128), token1_address);
+        });
+    }
+    #[test]
+    fn add_liquidity_optimal_amounts(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        // move the price to 6000 / 4169
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        AmmContractRef::at(&amm_contract).swap_for_exact_output(Balance::from(831u128), Balance::from(1000u128), token1_address);
+        // fund the user with 1200 token0 and 1000 token1
+        change_caller(test_env::get_account(0));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1200u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(169u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1200u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(1000u128));
+        let shares: Balance = AmmContractRef::at(&amm_contract).add_liquidity(Balance::from(1200u128), Balance::from(1000u128), Balance::from(1200u128), Balance::from(800u128));
+        // only 833 token1 match 1200 token0, the rest stays with the user
+        assert_eq!(shares, Balance::from(999u128));
+        assert_eq!(Erc20Ref::at(&amm_contract).balance_of(&user), Balance::from(4999u128));
+        assert_eq!(Erc20Ref::at(&token0_address).balance_of(&user), Balance::zero());
+        assert_eq!(Erc20Ref::at(&token1_address).balance_of(&user), Balance::from(167u128));
+        assert_eq!(AmmContractRef::at(&amm_contract).reserve0(), Balance::from(7200u128));
+        assert_eq!(AmmContractRef::at(&amm_contract).reserve1(), Balance::from(5002u128));
+    }
+    #[test]
+    fn add_liquidity_min_amounts(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(2000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(2000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(2000u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(2000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        // 1000 token0 match 1000 token1
+        test_env::assert_exception(Error::InsufficientAmount1, || {
+            amm.add_liquidity(Balance::from(1000u128), Balance::from(2000u128), Balance::zero(), Balance::from(1001u128));
+        });
+        // 1000 token1 match 1000 token0
+        test_env::assert_exception(Error::InsufficientAmount0, || {
+            amm.add_liquidity(Balance::from(2000u128), Balance::from(1000u128), Balance::from(1001u128), Balance::zero());
+        });
+    }
+    #[test]
+    fn remove_liquidity_bounds(){
+        let (user, _, _, amm_contract) = setup(Balance::from(5000u128));
+        let shares: Balance = Erc20Ref::at(&amm_contract).balance_of(&user);
+        change_caller(user);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        // the shares are worth 4000 of each token
+        test_env::assert_exception(Error::InsufficientAmount0, || {
+            amm.remove_liquidity(shares, Balance::from(4001u128), Balance::zero(), u64::MAX);
+        });
+        test_env::assert_exception(Error::InsufficientAmount1, || {
+            amm.remove_liquidity(shares, Balance::zero(), Balance::from(4001u128), u64::MAX);
+        });
+        test_env::advance_block_time_by(100);
+        test_env::assert_exception(Error::Expired, || {
+            amm.remove_liquidity(shares, Balance::zero(), Balance::zero(), 99);
+        });
+        let (amount0, amount1) = amm.remove_liquidity(shares, Balance::from(4000u128), Balance::from(4000u128), u64::MAX);
+        assert_eq!(amount0, Balance::from(4000u128));
+        assert_eq!(amount1, Balance::from(4000u128));
+    }
+    #[test]
+    fn events(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        assert_events!(
+            amm,
+            Sync {
+                reserve0: Balance::from(5000u128),
+                reserve1: Balance::from(5000u128)
+            },
+            Mint {
+                sender: user,
+                amount0: Balance::from(5000u128),
+                amount1: Balance::from(5000u128)
+            }
+        );
+        // swap
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        amm.swap(Balance::from(1000u128), token0_address);
+        assert_events!(
+            amm,
+            Sync {
+                reserve0: Balance::from(6000u128),
+                reserve1: Balance::from(4169u128)
+            },
+            Swap {
+                sender: user,
+                amount_in: Balance::from(1000u128),
+                amount_out: Balance::from(831u128),
+                token_in: token0_address,
+                to: user
+            }
+        );
+        // remove half of the liquidity
+        let shares: Balance = Erc20Ref::at(&amm_contract).balance_of(&user) / Balance::from(2u128);
+        amm.remove_liquidity(shares, Balance::zero(), Balance::zero(), u64::MAX);
+        assert_events!(
+            amm,
+            Transfer {
+                from: Some(user),
+                to: None,
+                amount: shares
+            },
+            Sync {
+                reserve0: Balance::from(3600u128),
+                reserve1: Balance::from(2502u128)
+            },
+            Burn {
+                sender: user,
+                amount0: Balance::from(2400u128),
+                amount1: Balance::from(1667u128),
+                to: user
+            }
+        );
+    }
+    #[test]
+    fn set_fee(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        assert_eq!(amm.fee(), 30);
+        // raise the fee to 1%
+        amm.set_fee(100);
+        assert_eq!(amm.fee(), 100);
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        amm.swap(Balance::from(1000u128), token0_address);
+        assert_eq!(Balance::from(826u128), Erc20Ref::at(&token1_address).balance_of(&user));
+    }
+    #[test]
+    fn set_fee_errors(){
+        let (user, _, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        test_env::assert_exception(Error::FeeTooHigh, || {
+            amm.set_fee(MAX_FEE + 1);
+        });
+        change_caller(user);
+        test_env::assert_exception(Error::NotAdmin, || {
+            amm.set_fee(0);
+        });
+        assert_eq!(amm.fee(), 30);
+    }
+    #[test]
+    fn protocol_fee(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(1_000_000u128));
+        let fee_to: Address = test_env::get_account(3);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        amm.set_fee_to(Some(fee_to));
+        assert_eq!(amm.fee_to(), Some(fee_to));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(101_000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(1_000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(101_000u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(1_000u128));
+        // the first liquidity event with the fee on records k
+        amm.add_liquidity(Balance::from(1_000u128), Balance::from(1_000u128), Balance::zero(), Balance::zero());
+        assert_eq!(amm.k_last(), Balance::from(1_001_000u128 * 1_001_000u128));
+        // trade back and forth to grow k
+        let amount_out: Balance = amm.swap(Balance::from(100_000u128), token0_address);
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &amount_out);
+        amm.swap(amount_out, token1_address);
+        // the next liquidity event mints 1/6th of the growth to fee_to
+        amm.remove_liquidity(Balance::from(1_000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(Erc20Ref::at(&amm_contract).balance_of(&fee_to), Balance::from(45u128));
+        assert_eq!(amm.k_last(), amm.reserve0() * amm.reserve1());
+        // switching the fee off clears k_last
+        change_caller(test_env::get_account(0));
+        amm.set_fee_to(None);
+        change_caller(user);
+        amm.remove_liquidity(Balance::from(1_000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(amm.k_last(), Balance::zero());
+        assert_eq!(Erc20Ref::at(&amm_contract).balance_of(&fee_to), Balance::from(45u128));
+    }
+    #[test]
+    fn set_fee_to_not_admin(){
+        let (user, _, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        change_caller(user);
+        test_env::assert_exception(Error::NotAdmin, || {
+            amm.set_fee_to(Some(user));
+        });
+        assert_eq!(amm.fee_to(), None);
+    }
+    #[test]
+    fn cumulative_prices(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        let start: u64 = amm.block_timestamp_last();
+        assert_eq!(amm.price0_cumulative_last(), Balance::zero());
+        // the price is 1 for 100 time units
+        test_env::advance_block_time_by(100);
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        amm.swap(Balance::from(1000u128), token0_address);
+        let q112: Balance = Balance::from(2u128).pow(Balance::from(112u128));
+        assert_eq!(amm.block_timestamp_last(), start + 100);
+        assert_eq!(amm.price0_cumulative_last(), q112 * Balance::from(100u128));
+        assert_eq!(amm.price1_cumulative_last(), q112 * Balance::from(100u128));
+        // the price is 4169 / 6000 for another 50 time units
+        test_env::advance_block_time_by(50);
+        amm.remove_liquidity(Balance::from(1u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(amm.block_timestamp_last(), start + 150);
+        assert_eq!(
+            amm.price0_cumulative_last(),
+            q112 * Balance::from(100u128) + q112 * Balance::from(4169u128) / Balance::from(6000u128) * Balance::from(50u128)
+        );
+        assert_eq!(
+            amm.price1_cumulative_last(),
+            q112 * Balance::from(100u128) + q112 * Balance::from(6000u128) / Balance::from(4169u128) * Balance::from(50u128)
+        );
+    }
+    #[test]
+    fn observe(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        let start: u64 = amm.block_timestamp_last();
+        amm.increase_observation_cardinality(3);
+        assert_eq!(amm.observation_cardinality(), 1);
+        assert_eq!(amm.observation_cardinality_next(), 3);
+        // price 1 for 100 time units
+        test_env::advance_block_time_by(100);
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        amm.swap(Balance::from(1000u128), token0_address);
+        assert_eq!(amm.observation_cardinality(), 3);
+        // price 4169 / 6000 for 100 time units
+        test_env::advance_block_time_by(100);
+        amm.remove_liquidity(Balance::from(1u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(amm.observation_index(), 2);
+        test_env::advance_block_time_by(50);
 
+        let q112: Balance = Balance::from(2u128).pow(Balance::from(112u128));
+        let price0_at_100: Balance = q112 * Balance::from(100u128);
+        let price0_at_200: Balance = price0_at_100 + q112 * Balance::from(4169u128) / Balance::from(6000u128) * Balance::from(100u128);
+        let observations: Vec<Observation> = amm.observe(vec![0, 50, 150, 200, 250]);
+        // extrapolated from the current reserves
+        assert_eq!(observations[0].timestamp, start + 250);
+        assert_eq!(observations[0].price0_cumulative, price0_at_200 + q112 * Balance::from(4169u128) / Balance::from(5999u128) * Balance::from(50u128));
+        // stored observations
+        assert_eq!(observations[1].price0_cumulative, price0_at_200);
+        assert_eq!(observations[1].liquidity_cumulative, Balance::from(5000u128 * 100 + 5001u128 * 100));
+        assert_eq!(observations[2].price0_cumulative, price0_at_100);
+        assert_eq!(observations[2].liquidity_cumulative, Balance::from(5000u128 * 100));
+        // interpolated between the first two observations
+        assert_eq!(observations[3].timestamp, start + 50);
+        assert_eq!(observations[3].price0_cumulative, q112 * Balance::from(50u128));
+        assert_eq!(observations[4].price0_cumulative, Balance::zero());
+        // the oldest observation is 250 time units old
+        test_env::assert_exception(OracleError::OldObservation, || {
+            amm.observe(vec![251]);
+        });
+    }
+    #[test]
+    fn observation_cardinality_cap(){
+        let (user, _, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        change_caller(user);
+        amm.increase_observation_cardinality(MAX_CARDINALITY);
+        assert_eq!(amm.observation_cardinality_next(), MAX_CARDINALITY);
+        test_env::assert_exception(OracleError::CardinalityTooHigh, || {
+            amm.increase_observation_cardinality(MAX_CARDINALITY + 1);
+        });
+        test_env::assert_exception(OracleError::CardinalityTooHigh, || {
+            amm.increase_observation_cardinality(u32::MAX);
+        });
+        // observations keep working once the buffer may grow to the cap
+        test_env::advance_block_time_by(100);
+        amm.sync();
+        assert_eq!(amm.observation_cardinality(), MAX_CARDINALITY);
+        assert_eq!(amm.observe(vec![0, 50]).len(), 2);
+    }
+    #[test]
+    fn flash_swap(){
+        let (user, _, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        // borrow 1000 token1 and pay back 1004 token1
+        let borrower: FlashBorrowerRef = FlashBorrowerDeployer::init(amm_contract, token1_address, Balance::from(1004u128));
+        Erc20Ref::at(&token1_address).mint(borrower.address(), &Balance::from(4u128));
+        change_caller(user);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        amm.flash_swap(Balance::zero(), Balance::from(1000u128), *borrower.address(), vec![1, 2, 3]);
+        assert_eq!(borrower.last_sender(), Some(user));
+        assert_eq!(borrower.last_amount(), Balance::from(1000u128));
+        assert_eq!(borrower.last_data(), vec![1, 2, 3]);
+        assert_eq!(Erc20Ref::at(&token1_address).balance_of(borrower.address()), Balance::zero());
+        assert_eq!(amm.reserve0(), Balance::from(5000u128));
+        assert_eq!(amm.reserve1(), Balance::from(5004u128));
+        assert_events!(
+            amm,
+            Sync {
+                reserve0: Balance::from(5000u128),
+                reserve1: Balance::from(5004u128)
+            },
+            FlashSwap {
+                sender: user,
+                amount0_in: Balance::zero(),
+                amount1_in: Balance::from(1004u128),
+                amount0_out: Balance::zero(),
+                amount1_out: Balance::from(1000u128),
+                to: *borrower.address()
+            }
+        );
+    }
+    #[test]
+    fn flash_swap_errors(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        // 1003 token1 do not cover the fee
+        let underpaying: FlashBorrowerRef = FlashBorrowerDeployer::init(amm_contract, token1_address, Balance::from(1003u128));
+        Erc20Ref::at(&token1_address).mint(underpaying.address(), &Balance::from(3u128));
+        let defaulting: FlashBorrowerRef = FlashBorrowerDeployer::init(amm_contract, token1_address, Balance::zero());
+        change_caller(user);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        test_env::assert_exception(Error::KInvariant, || {
+            amm.flash_swap(Balance::zero(), Balance::from(1000u128), *underpaying.address(), vec![]);
+        });
+        test_env::assert_exception(Error::InsufficientInputAmount, || {
+            amm.flash_swap(Balance::zero(), Balance::from(1000u128), *defaulting.address(), vec![]);
+        });
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            amm.flash_swap(Balance::zero(), Balance::zero(), *defaulting.address(), vec![]);
+        });
+        test_env::assert_exception(Error::InsufficientLiquidity, || {
+            amm.flash_swap(Balance::from(5000u128), Balance::zero(), *defaulting.address(), vec![]);
+        });
+        test_env::assert_exception(Error::InvalidTo, || {
+            amm.flash_swap(Balance::from(1000u128), Balance::zero(), token0_address, vec![]);
+        });
+    }
+    #[test]
+    fn flash_loan(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        assert_eq!(amm.max_flash_loan(token0_address), Balance::from(5000u128));
+        // 0.09% of 1000 rounded up
+        assert_eq!(amm.flash_fee(token0_address, Balance::from(1000u128)), Balance::from(1u128));
+        let borrower: FlashBorrowerRef = FlashBorrowerDeployer::init(amm_contract, token0_address, Balance::from(1001u128));
+        Erc20Ref::at(&token0_address).mint(borrower.address(), &Balance::from(1u128));
+        change_caller(user);
+        amm.flash_loan(*borrower.address(), token0_address, Balance::from(1000u128), vec![7]);
+        assert_eq!(borrower.last_sender(), Some(user));
+        assert_eq!(borrower.last_amount(), Balance::from(1001u128));
+        assert_eq!(borrower.last_data(), vec![7]);
+        // the fee accrues to the reserves
+        assert_eq!(amm.reserve0(), Balance::from(5001u128));
+        assert_eq!(amm.reserve1(), Balance::from(5000u128));
+        assert_events!(
+            amm,
+            Sync {
+                reserve0: Balance::from(5001u128),
+                reserve1: Balance::from(5000u128)
+            },
+            FlashLoan {
+                initiator: user,
+                receiver: *borrower.address(),
+                token: token0_address,
+                amount: Balance::from(1000u128),
+                fee: Balance::from(1u128)
+            }
+        );
+    }
+    #[test]
+    fn flash_loan_errors(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        // the fee is not paid back
+        let borrower: FlashBorrowerRef = FlashBorrowerDeployer::init(amm_contract, token0_address, Balance::from(1000u128));
+        test_env::assert_exception(Error::FeeTooHigh, || {
+            amm.set_flash_loan_fee(MAX_FEE + 1);
+        });
+        amm.set_flash_loan_fee(100);
+        assert_eq!(amm.flash_loan_fee(), 100);
+        change_caller(user);
+        test_env::assert_exception(Error::NotAdmin, || {
+            amm.set_flash_loan_fee(0);
+        });
+        test_env::assert_exception(Error::InsufficientRepayment, || {
+            amm.flash_loan(*borrower.address(), token0_address, Balance::from(1000u128), vec![]);
+        });
+        test_env::assert_exception(Error::InsufficientLiquidity, || {
+            amm.flash_loan(*borrower.address(), token0_address, Balance::from(5001u128), vec![]);
+        });
+        test_env::assert_exception(Error::InvalidToken, || {
+            amm.flash_loan(*borrower.address(), amm_contract, Balance::from(1000u128), vec![]);
+        });
+    }
+    #[test]
+    fn reentrancy(){
+        let user: Address = odra::test_env::get_account(1);
+        let mut hostile: HostileTokenRef = HostileTokenDeployer::init();
+        let hostile_address: Address = *hostile.address();
+        let token1_address: Address = Erc20Deployer::init("TOKEN1".to_string(), "TKN1".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
+        let amm_contract: Address = AmmContractDeployer::init(hostile_address, token1_address, 30, odra::test_env::get_account(0)).address().to_owned();
+        hostile.mint(&user, &Balance::from(7000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(6000u128));
+        change_caller(user);
+        hostile.approve(&amm_contract, &Balance::from(7000u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(6000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        amm.add_liquidity(Balance::from(5000u128), Balance::from(5000u128), Balance::zero(), Balance::zero());
+        // the token calls back into the pool from transfer_from
+        hostile.arm(amm_contract);
+        test_env::assert_exception(GuardError::Locked, || {
+            amm.swap(Balance::from(1000u128), hostile_address);
+        });
+        test_env::assert_exception(GuardError::Locked, || {
+            amm.add_liquidity(Balance::from(1000u128), Balance::from(1000u128), Balance::zero(), Balance::zero());
+        });
+        assert_eq!(amm.reserve0(), Balance::from(5000u128));
+        assert_eq!(amm.reserve1(), Balance::from(5000u128));
+        // the lock is released after every call
+        hostile.disarm();
+        amm.swap(Balance::from(1000u128), hostile_address);
+        assert_eq!(Erc20Ref::at(&token1_address).balance_of(&user), Balance::from(1831u128));
+    }
+    #[test]
+    fn skim_and_sync(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        let recipient: Address = test_env::get_account(3);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        // tokens sent directly to the pool are not part of the reserves
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(300u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(200u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).transfer(&amm_contract, &Balance::from(100u128));
+        Erc20Ref::at(&token1_address).transfer(&amm_contract, &Balance::from(200u128));
+        amm.skim(recipient);
+        assert_eq!(Erc20Ref::at(&token0_address).balance_of(&recipient), Balance::from(100u128));
+        assert_eq!(Erc20Ref::at(&token1_address).balance_of(&recipient), Balance::from(200u128));
+        assert_eq!(amm.reserve0(), Balance::from(5000u128));
+        assert_eq!(amm.reserve1(), Balance::from(5000u128));
+        assert_events!(
+            amm,
+            Skim {
+                to: recipient,
+                amount0: Balance::from(100u128),
+                amount1: Balance::from(200u128)
+            }
+        );
+        // sync adds them to the reserves instead
+        Erc20Ref::at(&token0_address).transfer(&amm_contract, &Balance::from(200u128));
+        amm.sync();
+        assert_eq!(amm.reserve0(), Balance::from(5200u128));
+        assert_eq!(amm.reserve1(), Balance::from(5000u128));
+        assert_events!(
+            amm,
+            Sync {
+                reserve0: Balance::from(5200u128),
+                reserve1: Balance::from(5000u128)
+            }
+        );
+    }
+    #[test]
+    fn quotes(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        assert_eq!(amm.quote(Balance::from(1000u128), Balance::from(5000u128), Balance::from(2500u128)), Balance::from(500u128));
+        // the swap quotes match the swaps
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(10000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(10000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(10000u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(10000u128));
+        let quoted_out: Balance = amm.get_amount_out(Balance::from(1000u128), token0_address);
+        assert_eq!(quoted_out, Balance::from(831u128));
+        assert_eq!(amm.swap(Balance::from(1000u128), token0_address), quoted_out);
+        let quoted_in: Balance = amm.get_amount_in(Balance::from(500u128), token0_address);
+        assert_eq!(amm.swap_for_exact_output(Balance::from(500u128), quoted_in, token0_address), quoted_in);
+        // the liquidity quotes match adding and removing liquidity
+        let quoted_shares: Balance = amm.quote_add_liquidity(Balance::from(1000u128), Balance::from(2000u128));
+        assert_eq!(amm.add_liquidity(Balance::from(1000u128), Balance::from(2000u128), Balance::zero(), Balance::zero()), quoted_shares);
+        let quoted_amounts: (Balance, Balance) = amm.quote_remove_liquidity(quoted_shares);
+        assert_eq!(amm.remove_liquidity(quoted_shares, Balance::zero(), Balance::zero(), u64::MAX), quoted_amounts);
+    }
+    #[test]
+    fn quotes_ignore_unsynced_balances(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1100u128));
+        change_caller(user);
+        // a donation is not part of the reserves until the next sync
+        Erc20Ref::at(&token0_address).transfer(&amm_contract, &Balance::from(100u128));
+        let quoted_out: Balance = amm.get_amount_out(Balance::from(1000u128), token0_address);
+        assert_eq!(quoted_out, Balance::from(831u128));
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(1000u128));
+        assert_eq!(amm.swap(Balance::from(1000u128), token0_address), quoted_out);
+        assert_eq!(amm.reserve0(), Balance::from(6100u128));
+    }
+    #[test]
+    fn quotes_errors(){
+        let (user, token0_address, _, amm_contract) = setup(Balance::from(5000u128));
+        let amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        test_env::assert_exception(Error::InvalidToken, || {
+            amm.get_amount_out(Balance::from(1000u128), user);
+        });
+        test_env::assert_exception(Error::InsufficientLiquidity, || {
+            amm.get_amount_in(Balance::from(5000u128), token0_address);
+        });
+        test_env::assert_exception(Error::InsufficientLiquidity, || {
+            amm.quote(Balance::from(1000u128), Balance::zero(), Balance::from(5000u128));
+        });
+    }
+    #[test]
+    fn quotes_protocol_fee(){
+        let (user, token0_address, token1_address, amm_contract) = setup(Balance::from(1_000_000u128));
+        let fee_to: Address = test_env::get_account(3);
+        let mut amm: AmmContractRef = AmmContractRef::at(&amm_contract);
+        amm.set_fee_to(Some(fee_to));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(103_000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(2_000u128));
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &Balance::from(103_000u128));
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &Balance::from(2_000u128));
+        amm.add_liquidity(Balance::from(1_000u128), Balance::from(1_000u128), Balance::zero(), Balance::zero());
+        let amount_out: Balance = amm.swap(Balance::from(100_000u128), token0_address);
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &amount_out);
+        amm.swap(amount_out, token1_address);
+        // the quotes account for the shares minted to fee_to by the next liquidity event
+        let quoted_amounts: (Balance, Balance) = amm.quote_remove_liquidity(Balance::from(1_000u128));
+        assert_eq!(amm.remove_liquidity(Balance::from(1_000u128), Balance::zero(), Balance::zero(), u64::MAX), quoted_amounts);
+        assert!(Erc20Ref::at(&amm_contract).balance_of(&fee_to) > Balance::zero());
+        let amount_out: Balance = amm.swap(Balance::from(1_000u128), token0_address);
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &(amount_out + Balance::from(1_000u128)));
+        amm.swap(amount_out, token1_address);
+        let quoted_shares: Balance = amm.quote_add_liquidity(Balance::from(1_000u128), Balance::from(1_000u128));
+        assert_eq!(amm.add_liquidity(Balance::from(1_000u128), Balance::from(1_000u128), Balance::zero(), Balance::zero()), quoted_shares);
+    }
+    // deploys a pool and seeds it with `amount` of both tokens provided by the user
+    fn setup(amount: Balance) -> (Address, Address, Address, Address){
+        let user: Address = odra::test_env::get_account(1);
+        let token0_address: Address = Erc20Deployer::init("TOKEN0".to_string(), "TKN0".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
+        let token1_address: Address = Erc20Deployer::init("TOKEN1".to_string(), "TKN1".to_string(), 18u8, &Balance::from(0u128)).address().to_owned();
+        let amm_contract: Address = AmmContractDeployer::init(token0_address, token1_address, 30, odra::test_env::get_account(0)).address().to_owned();
+        Erc20Ref::at(&token0_address).mint(&user, &amount);
+        Erc20Ref::at(&token1_address).mint(&user, &amount);
+        change_caller(user);
+        Erc20Ref::at(&token0_address).approve(&amm_contract, &amount);
+        Erc20Ref::at(&token1_address).approve(&amm_contract, &amount);
+        AmmContractRef::at(&amm_contract).add_liquidity(amount, amount, Balance::zero(), Balance::zero());
+        // token minting is done by the deployer
+        change_caller(odra::test_env::get_account(0));
+        (user, token0_address, token1_address, amm_contract)
+    }
+    // repays a fixed amount of `token` to the pool from its flash swap and flash loan callbacks
+    #[odra::module]
+    pub struct FlashBorrower {
+        pool: Variable<Address>,
+        token: Variable<Address>,
+        repay_amount: Variable<Balance>,
+        last_sender: Variable<Address>,
+        last_amount: Variable<Balance>,
+        last_data: Variable<Vec<u8>>
+    }
+    #[odra::module]
+    impl FlashBorrower {
+        #[odra(init)]
+        pub fn init(&mut self, pool: Address, token: Address, repay_amount: Balance){
+            self.pool.set(pool);
+            self.token.set(token);
+            self.repay_amount.set(repay_amount);
+        }
+        pub fn on_flash_swap(&mut self, sender: Address, amount0_out: Balance, amount1_out: Balance, data: Vec<u8>){
+            self.last_sender.set(sender);
+            self.last_amount.set(amount0_out + amount1_out);
+            self.last_data.set(data);
+            Erc20Ref::at(&self.token.get().unwrap()).transfer(&self.pool.get().unwrap(), &self.repay_amount.get().unwrap());
+        }
+        pub fn on_flash_loan(&mut self, initiator: Address, token: Address, amount: Balance, fee: Balance, data: Vec<u8>){
+            self.last_sender.set(initiator);
+            self.last_amount.set(amount + fee);
+            self.last_data.set(data);
+            Erc20Ref::at(&token).transfer(&self.pool.get().unwrap(), &self.repay_amount.get().unwrap());
+        }
+        pub fn last_sender(&self) -> Option<Address>{
+            self.last_sender.get()
+        }
+        pub fn last_amount(&self) -> Balance{
+            self.last_amount.get_or_default()
+        }
+        pub fn last_data(&self) -> Vec<u8>{
+            self.last_data.get_or_default()
+        }
+    }
+    // Erc20 calling back into `target` from transfer_from
+    #[odra::module]
+    pub struct HostileToken {
+        erc20: Erc20,
+        target: Variable<Option<Address>>
+    }
+    #[odra::module]
+    impl HostileToken {
+        #[odra(init)]
+        pub fn init(&mut self){
+            self.erc20.init("HOSTILE".to_string(), "HST".to_string(), 18u8, &Balance::from(0u128));
+        }
+        pub fn arm(&mut self, target: Address){
+            self.target.set(Some(target));
+        }
+        pub fn disarm(&mut self){
+            self.target.set(None);
+        }
+        pub fn mint(&mut self, address: &Address, amount: &Balance){
+            self.erc20.mint(address, amount);
+        }
+        pub fn approve(&mut self, spender: &Address, amount: &Balance){
+            self.erc20.approve(spender, amount);
+        }
+        pub fn balance_of(&self, address: &Address) -> Balance{
+            self.erc20.balance_of(address)
+        }
+        pub fn transfer(&mut self, recipient: &Address, amount: &Balance){
+            self.erc20.transfer(recipient, amount);
+        }
+        pub fn transfer_from(&mut self, owner: &Address, recipient: &Address, amount: &Balance){
+            if let Some(target) = self.target.get_or_default(){
+                AmmContractRef::at(&target).swap(*amount, contract_env::self_address());
+            }
+            self.erc20.transfer_from(owner, recipient, amount);
+        }
     }
     fn change_caller(caller: Address){
         odra::test_env::set_caller(caller);
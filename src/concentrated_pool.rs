@@ -0,0 +1,682 @@
+use odra::{Variable, Mapping, contract_env, execution_error, Event, OdraType};
+use odra::types::{Address, Balance};
+use odra::types::event::OdraEvent;
+use crate::amm::MAX_FEE;
+use crate::erc20::Erc20Ref;
+use crate::math::{_get_sqrt_ratio_at_tick, _get_tick_at_sqrt_ratio, _get_amount0_delta, _get_amount1_delta, _compute_swap_step, _add_mod_256, _sub_mod_256, _max_sqrt_ratio, MIN_TICK, MAX_TICK, MIN_SQRT_RATIO};
+use crate::reentrancy_guard::ReentrancyGuard;
+
+// liquidity referencing an initialized tick, `liquidity_lower` belongs to positions starting at the tick
+// and `liquidity_upper` to positions ending at it, fee growth is tracked on the side away from the price
+#[derive(OdraType, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Tick {
+    pub liquidity_gross: Balance,
+    pub liquidity_lower: Balance,
+    pub liquidity_upper: Balance,
+    pub fee_growth_outside0_x128: Balance,
+    pub fee_growth_outside1_x128: Balance
+}
+
+#[derive(OdraType, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Position {
+    pub liquidity: Balance,
+    pub fee_growth_inside0_last_x128: Balance,
+    pub fee_growth_inside1_last_x128: Balance,
+    pub tokens_owed0: Balance,
+    pub tokens_owed1: Balance
+}
+
+// pool concentrating liquidity in tick ranges, prices are Q64.96 square roots of token1 per token0
+#[odra::module(events = [Mint, Burn, Collect, Swap])]
+pub struct ConcentratedPool {
+    token0_address: Variable<Address>,
+    token1_address: Variable<Address>,
+    fee: Variable<u32>,
+    tick_spacing: Variable<i32>,
+    sqrt_price_x96: Variable<Balance>,
+    tick: Variable<i32>,
+    liquidity: Variable<Balance>,
+    fee_growth_global0_x128: Variable<Balance>,
+    fee_growth_global1_x128: Variable<Balance>,
+    ticks: Mapping<i32, Tick>,
+    // doubly linked list of the initialized ticks between the MIN_TICK and MAX_TICK sentinels
+    next_tick: Mapping<i32, i32>,
+    prev_tick: Mapping<i32, i32>,
+    // greatest initialized tick at or below the current tick
+    tick_below: Variable<i32>,
+    positions: Mapping<Address, Mapping<i32, Mapping<i32, Position>>>,
+    guard: ReentrancyGuard
+}
+
+execution_error! {
+    pub enum Error {
+        InvalidTickRange => 1,
+        InvalidTickSpacing => 2,
+        InvalidPrice => 3,
+        InvalidPriceLimit => 4,
+        ZeroLiquidity => 5,
+        InsufficientLiquidity => 6,
+        InsufficientInputAmount => 7,
+        InsufficientOutputAmount => 8,
+        FeeTooHigh => 9,
+        IdenticalAddresses => 10,
+        UnsortedTokens => 11,
+    }
+}
+
+#[odra::module]
+impl ConcentratedPool {
+    #[odra(init)]
+    pub fn init(&mut self, token0_address: Address, token1_address: Address, fee: u32, tick_spacing: i32, sqrt_price_x96: Balance) {
+        if token0_address == token1_address {
+            contract_env::revert(Error::IdenticalAddresses)
+        }
+        // the price is quoted in token1 per token0, so the order of the tokens has to be canonical
+        if token0_address > token1_address {
+            contract_env::revert(Error::UnsortedTokens)
+        }
+        if fee > MAX_FEE {
+            contract_env::revert(Error::FeeTooHigh)
+        }
+        if tick_spacing <= 0 {
+            contract_env::revert(Error::InvalidTickSpacing)
+        }
+        if sqrt_price_x96 < Balance::from(MIN_SQRT_RATIO) || sqrt_price_x96 >= _max_sqrt_ratio() {
+            contract_env::revert(Error::InvalidPrice)
+        }
+        self.token0_address.set(token0_address);
+        self.token1_address.set(token1_address);
+        self.fee.set(fee);
+        self.tick_spacing.set(tick_spacing);
+        self.sqrt_price_x96.set(sqrt_price_x96);
+        self.tick.set(_get_tick_at_sqrt_ratio(sqrt_price_x96));
+        self.liquidity.set(Balance::zero());
+        self.fee_growth_global0_x128.set(Balance::zero());
+        self.fee_growth_global1_x128.set(Balance::zero());
+        self.next_tick.set(&MIN_TICK, MAX_TICK);
+        self.prev_tick.set(&MAX_TICK, MIN_TICK);
+        self.tick_below.set(MIN_TICK);
+    }
+
+    // adds `liquidity` to the position of the caller, the caller pays the token amounts
+    pub fn mint(&mut self, tick_lower: i32, tick_upper: i32, liquidity: Balance) -> (Balance, Balance) {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        self.check_ticks(tick_lower, tick_upper);
+        if liquidity == Balance::zero() {
+            contract_env::revert(Error::ZeroLiquidity)
+        }
+        self.update_tick(tick_lower, liquidity, false, true);
+        self.update_tick(tick_upper, liquidity, true, true);
+        self.update_position(caller, tick_lower, tick_upper, liquidity, true);
+        let (amount0, amount1) = self.amounts_for_liquidity(tick_lower, tick_upper, liquidity, true);
+        if self.in_range(tick_lower, tick_upper) {
+            self.liquidity.add(liquidity);
+        }
+        if amount0 > Balance::zero() {
+            Erc20Ref::at(&self.token0_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount0);
+        }
+        if amount1 > Balance::zero() {
+            Erc20Ref::at(&self.token1_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount1);
+        }
+        Mint {
+            owner: caller,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
+    }
+
+    // removes `liquidity` from the position of the caller and credits the tokens to the position,
+    // burning zero liquidity only updates the fees owed of a position that still holds liquidity
+    pub fn burn(&mut self, tick_lower: i32, tick_upper: i32, liquidity: Balance) -> (Balance, Balance) {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        self.check_ticks(tick_lower, tick_upper);
+        let position_liquidity: Balance = self.position(caller, tick_lower, tick_upper).liquidity;
+        if position_liquidity == Balance::zero() {
+            contract_env::revert(Error::ZeroLiquidity)
+        }
+        if liquidity > position_liquidity {
+            contract_env::revert(Error::InsufficientLiquidity)
+        }
+        self.update_position(caller, tick_lower, tick_upper, liquidity, false);
+        let (amount0, amount1) = self.amounts_for_liquidity(tick_lower, tick_upper, liquidity, false);
+        if liquidity > Balance::zero() {
+            if self.in_range(tick_lower, tick_upper) {
+                self.liquidity.set(self.liquidity.get_or_default() - liquidity);
+            }
+            self.update_tick(tick_lower, liquidity, false, false);
+            self.update_tick(tick_upper, liquidity, true, false);
+        }
+        let mut position: Position = self.position(caller, tick_lower, tick_upper);
+        position.tokens_owed0 = position.tokens_owed0 + amount0;
+        position.tokens_owed1 = position.tokens_owed1 + amount1;
+        self.positions.get_instance(&caller).get_instance(&tick_lower).set(&tick_upper, position);
+        Burn {
+            owner: caller,
+            tick_lower,
+            tick_upper,
+            liquidity,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
+    }
+
+    // transfers up to the requested amounts of the tokens owed to the position of the caller
+    pub fn collect(&mut self, tick_lower: i32, tick_upper: i32, amount0_requested: Balance, amount1_requested: Balance) -> (Balance, Balance) {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        let mut position: Position = self.position(caller, tick_lower, tick_upper);
+        let amount0: Balance = if amount0_requested > position.tokens_owed0 { position.tokens_owed0 } else { amount0_requested };
+        let amount1: Balance = if amount1_requested > position.tokens_owed1 { position.tokens_owed1 } else { amount1_requested };
+        position.tokens_owed0 = position.tokens_owed0 - amount0;
+        position.tokens_owed1 = position.tokens_owed1 - amount1;
+        self.positions.get_instance(&caller).get_instance(&tick_lower).set(&tick_upper, position);
+        if amount0 > Balance::zero() {
+            Erc20Ref::at(&self.token0_address.get().unwrap()).transfer(&caller, &amount0);
+        }
+        if amount1 > Balance::zero() {
+            Erc20Ref::at(&self.token1_address.get().unwrap()).transfer(&caller, &amount1);
+        }
+        Collect {
+            owner: caller,
+            tick_lower,
+            tick_upper,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
+    }
+
+    // exact input swap crossing initialized ticks until the input is used up or the price reaches
+    // `sqrt_price_limit_x96`, returns the input used and the output
+    pub fn swap(&mut self, zero_for_one: bool, amount_in: Balance, sqrt_price_limit_x96: Balance, min_amount_out: Balance) -> (Balance, Balance) {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        if amount_in == Balance::zero() {
+            contract_env::revert(Error::InsufficientInputAmount)
+        }
+        let mut sqrt_price_x96: Balance = self.sqrt_price_x96.get().unwrap();
+        let valid_limit: bool = if zero_for_one {
+            sqrt_price_limit_x96 < sqrt_price_x96 && sqrt_price_limit_x96 > Balance::from(MIN_SQRT_RATIO)
+        } else {
+            sqrt_price_limit_x96 > sqrt_price_x96 && sqrt_price_limit_x96 < _max_sqrt_ratio()
+        };
+        if !valid_limit {
+            contract_env::revert(Error::InvalidPriceLimit)
+        }
+        let mut tick: i32 = self.tick.get().unwrap();
+        let mut tick_below: i32 = self.tick_below.get().unwrap();
+        let mut liquidity: Balance = self.liquidity.get_or_default();
+        let mut fee_growth_global0_x128: Balance = self.fee_growth_global0_x128.get_or_default();
+        let mut fee_growth_global1_x128: Balance = self.fee_growth_global1_x128.get_or_default();
+        let fee: u32 = self.fee.get().unwrap();
+        let mut amount_remaining: Balance = amount_in;
+        let mut amount_out: Balance = Balance::zero();
+        while amount_remaining > Balance::zero() && sqrt_price_x96 != sqrt_price_limit_x96 {
+            let next: i32 = if zero_for_one { tick_below } else { self.next_tick.get(&tick_below).unwrap() };
+            let sqrt_price_next_x96: Balance = _get_sqrt_ratio_at_tick(next);
+            let sqrt_price_target_x96: Balance = if zero_for_one {
+                if sqrt_price_next_x96 < sqrt_price_limit_x96 { sqrt_price_limit_x96 } else { sqrt_price_next_x96 }
+            } else if sqrt_price_next_x96 > sqrt_price_limit_x96 {
+                sqrt_price_limit_x96
+            } else {
+                sqrt_price_next_x96
+            };
+            let (sqrt_price_step_x96, step_in, step_out, step_fee) = _compute_swap_step(sqrt_price_x96, sqrt_price_target_x96, liquidity, amount_remaining, fee);
+            amount_remaining = amount_remaining - step_in - step_fee;
+            amount_out = amount_out + step_out;
+            // the fee is shared by the liquidity in range
+            if liquidity > Balance::zero() {
+                let growth: Balance = (step_fee << 128) / liquidity;
+                if zero_for_one {
+                    fee_growth_global0_x128 = _add_mod_256(fee_growth_global0_x128, growth);
+                } else {
+                    fee_growth_global1_x128 = _add_mod_256(fee_growth_global1_x128, growth);
+                }
+            }
+            if sqrt_price_step_x96 == sqrt_price_next_x96 {
+                // cross the tick, the fee growth outside flips to the other side
+                let mut crossed: Tick = self.ticks.get_or_default(&next);
+                crossed.fee_growth_outside0_x128 = _sub_mod_256(fee_growth_global0_x128, crossed.fee_growth_outside0_x128);
+                crossed.fee_growth_outside1_x128 = _sub_mod_256(fee_growth_global1_x128, crossed.fee_growth_outside1_x128);
+                self.ticks.set(&next, crossed);
+                if zero_for_one {
+                    liquidity = liquidity + crossed.liquidity_upper - crossed.liquidity_lower;
+                    tick_below = self.prev_tick.get(&next).unwrap();
+                    tick = next - 1;
+                } else {
+                    liquidity = liquidity + crossed.liquidity_lower - crossed.liquidity_upper;
+                    tick_below = next;
+                    tick = next;
+                }
+            } else if sqrt_price_step_x96 != sqrt_price_x96 {
+                tick = _get_tick_at_sqrt_ratio(sqrt_price_step_x96);
+            }
+            sqrt_price_x96 = sqrt_price_step_x96;
+        }
+        if amount_out < min_amount_out {
+            contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        self.sqrt_price_x96.set(sqrt_price_x96);
+        self.tick.set(tick);
+        self.tick_below.set(tick_below);
+        self.liquidity.set(liquidity);
+        self.fee_growth_global0_x128.set(fee_growth_global0_x128);
+        self.fee_growth_global1_x128.set(fee_growth_global1_x128);
+        let amount_used: Balance = amount_in - amount_remaining;
+        let (token_in, token_out) = if zero_for_one {
+            (self.token0_address.get().unwrap(), self.token1_address.get().unwrap())
+        } else {
+            (self.token1_address.get().unwrap(), self.token0_address.get().unwrap())
+        };
+        Erc20Ref::at(&token_in).transfer_from(&caller, &contract_env::self_address(), &amount_used);
+        if amount_out > Balance::zero() {
+            Erc20Ref::at(&token_out).transfer(&caller, &amount_out);
+        }
+        Swap {
+            sender: caller,
+            zero_for_one,
+            amount_in: amount_used,
+            amount_out,
+            sqrt_price_x96,
+            liquidity,
+            tick
+        }
+        .emit();
+        self.guard.unlock();
+        (amount_used, amount_out)
+    }
+
+    pub fn sqrt_price_x96(&self) -> Balance {
+        self.sqrt_price_x96.get().unwrap()
+    }
+
+    pub fn tick(&self) -> i32 {
+        self.tick.get().unwrap()
+    }
+
+    pub fn liquidity(&self) -> Balance {
+        self.liquidity.get_or_default()
+    }
+
+    pub fn fee_growth_global0_x128(&self) -> Balance {
+        self.fee_growth_global0_x128.get_or_default()
+    }
+
+    pub fn fee_growth_global1_x128(&self) -> Balance {
+        self.fee_growth_global1_x128.get_or_default()
+    }
+
+    pub fn ticks(&self, tick: i32) -> Option<Tick> {
+        self.ticks.get(&tick)
+    }
+
+    pub fn position(&self, owner: Address, tick_lower: i32, tick_upper: i32) -> Position {
+        self.positions.get_instance(&owner).get_instance(&tick_lower).get_or_default(&tick_upper)
+    }
+
+    pub fn fee(&self) -> u32 {
+        self.fee.get().unwrap()
+    }
+
+    pub fn tick_spacing(&self) -> i32 {
+        self.tick_spacing.get().unwrap()
+    }
+
+    pub fn token0(&self) -> Address {
+        self.token0_address.get().unwrap()
+    }
+
+    pub fn token1(&self) -> Address {
+        self.token1_address.get().unwrap()
+    }
+
+    fn check_ticks(&self, tick_lower: i32, tick_upper: i32) {
+        if tick_lower >= tick_upper || tick_lower <= MIN_TICK || tick_upper >= MAX_TICK {
+            contract_env::revert(Error::InvalidTickRange)
+        }
+        let tick_spacing: i32 = self.tick_spacing.get().unwrap();
+        if tick_lower % tick_spacing != 0 || tick_upper % tick_spacing != 0 {
+            contract_env::revert(Error::InvalidTickSpacing)
+        }
+    }
+
+    fn in_range(&self, tick_lower: i32, tick_upper: i32) -> bool {
+        let tick: i32 = self.tick.get().unwrap();
+        tick >= tick_lower && tick < tick_upper
+    }
+
+    // token amounts backing `liquidity` in the range at the current price
+    fn amounts_for_liquidity(&self, tick_lower: i32, tick_upper: i32, liquidity: Balance, round_up: bool) -> (Balance, Balance) {
+        let sqrt_ratio_lower: Balance = _get_sqrt_ratio_at_tick(tick_lower);
+        let sqrt_ratio_upper: Balance = _get_sqrt_ratio_at_tick(tick_upper);
+        let tick: i32 = self.tick.get().unwrap();
+        if tick < tick_lower {
+            return (_get_amount0_delta(sqrt_ratio_lower, sqrt_ratio_upper, liquidity, round_up), Balance::zero());
+        }
+        if tick < tick_upper {
+            let sqrt_price_x96: Balance = self.sqrt_price_x96.get().unwrap();
+            return (
+                _get_amount0_delta(sqrt_price_x96, sqrt_ratio_upper, liquidity, round_up),
+                _get_amount1_delta(sqrt_ratio_lower, sqrt_price_x96, liquidity, round_up)
+            );
+        }
+        (Balance::zero(), _get_amount1_delta(sqrt_ratio_lower, sqrt_ratio_upper, liquidity, round_up))
+    }
+
+    // adds or removes liquidity referencing `tick`, initializing and clearing the tick as needed
+    fn update_tick(&mut self, tick: i32, liquidity: Balance, upper: bool, add: bool) {
+        let mut info: Tick = self.ticks.get_or_default(&tick);
+        if info.liquidity_gross == Balance::zero() {
+            // by convention all fee growth so far happened below an initialized tick at or below the price
+            if tick <= self.tick.get().unwrap() {
+                info.fee_growth_outside0_x128 = self.fee_growth_global0_x128.get_or_default();
+                info.fee_growth_outside1_x128 = self.fee_growth_global1_x128.get_or_default();
+            }
+            self.insert_tick(tick);
+        }
+        if add {
+            info.liquidity_gross = info.liquidity_gross + liquidity;
+            if upper {
+                info.liquidity_upper = info.liquidity_upper + liquidity;
+            } else {
+                info.liquidity_lower = info.liquidity_lower + liquidity;
+            }
+        } else {
+            info.liquidity_gross = info.liquidity_gross - liquidity;
+            if upper {
+                info.liquidity_upper = info.liquidity_upper - liquidity;
+            } else {
+                info.liquidity_lower = info.liquidity_lower - liquidity;
+            }
+        }
+        if info.liquidity_gross == Balance::zero() {
+            self.ticks.set(&tick, Tick::default());
+            self.remove_tick(tick);
+        } else {
+            self.ticks.set(&tick, info);
+        }
+    }
+
+    // links `tick` into the list, walking from the tick below the price
+    fn insert_tick(&mut self, tick: i32) {
+        let mut before: i32 = self.tick_below.get().unwrap();
+        if tick > before {
+            while self.next_tick.get(&before).unwrap() < tick {
+                before = self.next_tick.get(&before).unwrap();
+            }
+        } else {
+            while before > tick {
+                before = self.prev_tick.get(&before).unwrap();
+            }
+        }
+        let after: i32 = self.next_tick.get(&before).unwrap();
+        self.next_tick.set(&before, tick);
+        self.prev_tick.set(&tick, before);
+        self.next_tick.set(&tick, after);
+        self.prev_tick.set(&after, tick);
+        if tick <= self.tick.get().unwrap() && tick > self.tick_below.get().unwrap() {
+            self.tick_below.set(tick);
+        }
+    }
+
+    fn remove_tick(&mut self, tick: i32) {
+        let before: i32 = self.prev_tick.get(&tick).unwrap();
+        let after: i32 = self.next_tick.get(&tick).unwrap();
+        self.next_tick.set(&before, after);
+        self.prev_tick.set(&after, before);
+        if self.tick_below.get().unwrap() == tick {
+            self.tick_below.set(before);
+        }
+    }
+
+    fn fee_growth_inside(&self, tick_lower: i32, tick_upper: i32) -> (Balance, Balance) {
+        let tick: i32 = self.tick.get().unwrap();
+        let lower: Tick = self.ticks.get_or_default(&tick_lower);
+        let upper: Tick = self.ticks.get_or_default(&tick_upper);
+        let global0: Balance = self.fee_growth_global0_x128.get_or_default();
+        let global1: Balance = self.fee_growth_global1_x128.get_or_default();
+        let (below0, below1) = if tick >= tick_lower {
+            (lower.fee_growth_outside0_x128, lower.fee_growth_outside1_x128)
+        } else {
+            (_sub_mod_256(global0, lower.fee_growth_outside0_x128), _sub_mod_256(global1, lower.fee_growth_outside1_x128))
+        };
+        let (above0, above1) = if tick < tick_upper {
+            (upper.fee_growth_outside0_x128, upper.fee_growth_outside1_x128)
+        } else {
+            (_sub_mod_256(global0, upper.fee_growth_outside0_x128), _sub_mod_256(global1, upper.fee_growth_outside1_x128))
+        };
+        (
+            _sub_mod_256(_sub_mod_256(global0, below0), above0),
+            _sub_mod_256(_sub_mod_256(global1, below1), above1)
+        )
+    }
+
+    // credits the fees earned since the last update and changes the liquidity of the position
+    fn update_position(&mut self, owner: Address, tick_lower: i32, tick_upper: i32, liquidity: Balance, add: bool) {
+        let mut position: Position = self.position(owner, tick_lower, tick_upper);
+        let (inside0, inside1) = self.fee_growth_inside(tick_lower, tick_upper);
+        position.tokens_owed0 = position.tokens_owed0 + ((_sub_mod_256(inside0, position.fee_growth_inside0_last_x128) * position.liquidity) >> 128);
+        position.tokens_owed1 = position.tokens_owed1 + ((_sub_mod_256(inside1, position.fee_growth_inside1_last_x128) * position.liquidity) >> 128);
+        position.fee_growth_inside0_last_x128 = inside0;
+        position.fee_growth_inside1_last_x128 = inside1;
+        if add {
+            position.liquidity = position.liquidity + liquidity;
+        } else {
+            position.liquidity = position.liquidity - liquidity;
+        }
+        self.positions.get_instance(&owner).get_instance(&tick_lower).set(&tick_upper, position);
+    }
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Mint {
+    pub owner: Address,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: Balance,
+    pub amount0: Balance,
+    pub amount1: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Burn {
+    pub owner: Address,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub liquidity: Balance,
+    pub amount0: Balance,
+    pub amount1: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Collect {
+    pub owner: Address,
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub amount0: Balance,
+    pub amount1: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Swap {
+    pub sender: Address,
+    pub zero_for_one: bool,
+    pub amount_in: Balance,
+    pub amount_out: Balance,
+    pub sqrt_price_x96: Balance,
+    pub liquidity: Balance,
+    pub tick: i32
+}
+
+#[cfg(test)]
+mod tests {
+    use odra::{assert_events, test_env};
+    use odra::types::{Address, Balance};
+    use crate::erc20::{Erc20Deployer, Erc20Ref};
+    use crate::math::{_get_sqrt_ratio_at_tick, MIN_SQRT_RATIO};
+    use crate::factory::sort_tokens;
+    use super::{Collect, ConcentratedPoolDeployer, ConcentratedPoolRef, Error};
+
+    fn deploy_token(symbol: &str) -> Address {
+        Erc20Deployer::init(symbol.to_string(), symbol.to_string(), 18u8, &Balance::from(0u128)).address().to_owned()
+    }
+
+    // pool at a price of 1 with a 0.3% fee and a tick spacing of 60
+    fn setup() -> ConcentratedPoolRef {
+        let (token0_address, token1_address) = sort_tokens(deploy_token("TKN0"), deploy_token("TKN1"));
+        ConcentratedPoolDeployer::init(token0_address, token1_address, 30, 60, Balance::from(1u128) << 96)
+    }
+
+    fn fund(pool: &ConcentratedPoolRef, account: Address, amount: Balance) {
+        // token minting is done by the deployer
+        test_env::set_caller(test_env::get_account(0));
+        Erc20Ref::at(&pool.token0()).mint(&account, &amount);
+        Erc20Ref::at(&pool.token1()).mint(&account, &amount);
+        test_env::set_caller(account);
+        Erc20Ref::at(&pool.token0()).approve(pool.address(), &amount);
+        Erc20Ref::at(&pool.token1()).approve(pool.address(), &amount);
+    }
+
+    #[test]
+    fn mint_and_burn() {
+        let user: Address = test_env::get_account(1);
+        let mut pool: ConcentratedPoolRef = setup();
+        assert_eq!(pool.tick(), 0);
+        fund(&pool, user, Balance::from(100_000u128));
+        // a range around the price takes both tokens
+        let amounts: (Balance, Balance) = pool.mint(-600, 600, Balance::from(1_000_000u128));
+        assert_eq!(amounts, (Balance::from(29_554u128), Balance::from(29_554u128)));
+        assert_eq!(pool.liquidity(), Balance::from(1_000_000u128));
+        assert_eq!(pool.ticks(-600).unwrap().liquidity_lower, Balance::from(1_000_000u128));
+        assert_eq!(pool.ticks(600).unwrap().liquidity_upper, Balance::from(1_000_000u128));
+        // a range above the price only takes token0
+        let above: (Balance, Balance) = pool.mint(600, 1200, Balance::from(1_000_000u128));
+        assert_eq!(above.1, Balance::zero());
+        assert_eq!(pool.liquidity(), Balance::from(1_000_000u128));
+        // burning credits the tokens, rounded down, and collecting pays them out
+        let burned: (Balance, Balance) = pool.burn(-600, 600, Balance::from(1_000_000u128));
+        assert_eq!(burned, (Balance::from(29_553u128), Balance::from(29_553u128)));
+        assert_eq!(pool.liquidity(), Balance::zero());
+        assert_eq!(pool.position(user, -600, 600).tokens_owed0, Balance::from(29_553u128));
+        let collected: (Balance, Balance) = pool.collect(-600, 600, Balance::from(u128::MAX), Balance::from(10_000u128));
+        assert_eq!(collected, (Balance::from(29_553u128), Balance::from(10_000u128)));
+        assert_eq!(pool.position(user, -600, 600).tokens_owed1, Balance::from(19_553u128));
+        assert_eq!(Erc20Ref::at(&pool.token1()).balance_of(&user), Balance::from(100_000u128 - 29_554u128 + 10_000u128));
+        assert_events!(pool, Collect { owner: user, tick_lower: -600, tick_upper: 600, amount0: Balance::from(29_553u128), amount1: Balance::from(10_000u128) });
+    }
+
+    #[test]
+    fn swap_and_fees() {
+        let user: Address = test_env::get_account(1);
+        let trader: Address = test_env::get_account(2);
+        let mut pool: ConcentratedPoolRef = setup();
+        fund(&pool, user, Balance::from(100_000u128));
+        pool.mint(-600, 600, Balance::from(1_000_000u128));
+        fund(&pool, trader, Balance::from(10_000u128));
+        let amounts: (Balance, Balance) = pool.swap(true, Balance::from(10_000u128), Balance::from(MIN_SQRT_RATIO + 1), Balance::zero());
+        assert_eq!(amounts, (Balance::from(10_000u128), Balance::from(9_871u128)));
+        assert_eq!(pool.tick(), -199);
+        assert_eq!(Erc20Ref::at(&pool.token1()).balance_of(&trader), Balance::from(19_871u128));
+        // burning zero liquidity credits the fees of the only position in range
+        test_env::set_caller(user);
+        pool.burn(-600, 600, Balance::zero());
+        assert_eq!(pool.position(user, -600, 600).tokens_owed0, Balance::from(29u128));
+        assert_eq!(pool.collect(-600, 600, Balance::from(u128::MAX), Balance::from(u128::MAX)), (Balance::from(29u128), Balance::zero()));
+    }
+
+    #[test]
+    fn swap_crosses_ticks() {
+        let user: Address = test_env::get_account(1);
+        let other: Address = test_env::get_account(2);
+        let trader: Address = test_env::get_account(3);
+        let mut pool: ConcentratedPoolRef = setup();
+        fund(&pool, user, Balance::from(100_000u128));
+        pool.mint(-600, 600, Balance::from(1_000_000u128));
+        fund(&pool, other, Balance::from(100_000u128));
+        assert_eq!(pool.mint(-1200, -600, Balance::from(2_000_000u128)), (Balance::zero(), Balance::from(57_360u128)));
+        // the swap runs out of the first range and continues in the second one
+        fund(&pool, trader, Balance::from(40_000u128));
+        let amounts: (Balance, Balance) = pool.swap(true, Balance::from(40_000u128), Balance::from(MIN_SQRT_RATIO + 1), Balance::zero());
+        assert_eq!(amounts, (Balance::from(40_000u128), Balance::from(38_389u128)));
+        assert_eq!(pool.tick(), -692);
+        assert_eq!(pool.liquidity(), Balance::from(2_000_000u128));
+        // each position earns the fees of the part of the swap within its range
+        test_env::set_caller(user);
+        assert_eq!(pool.burn(-600, 600, Balance::from(1_000_000u128)), (Balance::from(60_005u128), Balance::zero()));
+        assert_eq!(pool.position(user, -600, 600).tokens_owed0, Balance::from(60_096u128));
+        test_env::set_caller(other);
+        pool.burn(-1200, -600, Balance::zero());
+        assert_eq!(pool.position(other, -1200, -600).tokens_owed0, Balance::from(28u128));
+    }
+
+    #[test]
+    fn swap_price_limit() {
+        let user: Address = test_env::get_account(1);
+        let mut pool: ConcentratedPoolRef = setup();
+        fund(&pool, user, Balance::from(100_000u128));
+        pool.mint(-600, 600, Balance::from(1_000_000u128));
+        // the swap stops at the limit and only pulls the input it used
+        let limit: Balance = _get_sqrt_ratio_at_tick(-60);
+        let amounts: (Balance, Balance) = pool.swap(true, Balance::from(40_000u128), limit, Balance::zero());
+        assert_eq!(amounts, (Balance::from(3_015u128), Balance::from(2_995u128)));
+        assert_eq!(pool.sqrt_price_x96(), limit);
+        assert_eq!(pool.tick(), -60);
+        test_env::assert_exception(Error::InvalidPriceLimit, || {
+            pool.swap(true, Balance::from(1_000u128), limit, Balance::zero());
+        });
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            pool.swap(true, Balance::from(1_000u128), Balance::from(MIN_SQRT_RATIO + 1), Balance::from(1_000u128));
+        });
+    }
+
+    #[test]
+    fn invalid_positions() {
+        let user: Address = test_env::get_account(1);
+        let mut pool: ConcentratedPoolRef = setup();
+        fund(&pool, user, Balance::from(100_000u128));
+        test_env::assert_exception(Error::InvalidTickRange, || {
+            pool.mint(600, -600, Balance::from(1_000u128));
+        });
+        test_env::assert_exception(Error::InvalidTickSpacing, || {
+            pool.mint(-600, 610, Balance::from(1_000u128));
+        });
+        test_env::assert_exception(Error::ZeroLiquidity, || {
+            pool.mint(-600, 600, Balance::zero());
+        });
+        // positions without liquidity cannot be burned, not even for zero
+        test_env::assert_exception(Error::ZeroLiquidity, || {
+            pool.burn(-600, 600, Balance::from(1u128));
+        });
+        test_env::assert_exception(Error::ZeroLiquidity, || {
+            pool.burn(-600, 600, Balance::zero());
+        });
+        pool.mint(-600, 600, Balance::from(1_000u128));
+        test_env::assert_exception(Error::InsufficientLiquidity, || {
+            pool.burn(-600, 600, Balance::from(1_001u128));
+        });
+    }
+
+    #[test]
+    fn invalid_tokens() {
+        let (token0_address, token1_address) = sort_tokens(deploy_token("TKN0"), deploy_token("TKN1"));
+        test_env::assert_exception(Error::IdenticalAddresses, || {
+            ConcentratedPoolDeployer::init(token0_address, token0_address, 30, 60, Balance::from(1u128) << 96);
+        });
+        test_env::assert_exception(Error::UnsortedTokens, || {
+            ConcentratedPoolDeployer::init(token1_address, token0_address, 30, 60, Balance::from(1u128) << 96);
+        });
+    }
+}
@@ -1,25 +1,28 @@
 use odra::{Variable, Mapping, contract_env, execution_error, Event};
 use odra::types::{Balance, Address, address};
 use odra::types::event::OdraEvent;
+use crate::access_control::{AccessControl, Role, RoleGranted, RoleRevoked, DEFAULT_ADMIN_ROLE, MINTER_ROLE};
 
-#[odra::module(events = [Transfer, Approval])]
+#[odra::module(events = [Transfer, Approval, RoleGranted, RoleRevoked])]
 pub struct Erc20 {
     decimals: Variable<u8>,
     symbol: Variable<String>,
     name: Variable<String>,
     total_supply: Variable<Balance>,
     balances: Mapping<Address, Balance>,
-    allowances: Mapping<Address, Mapping<Address, Balance>>
+    allowances: Mapping<Address, Mapping<Address, Balance>>,
+    access_control: AccessControl
 }
 #[odra::module]
 impl Erc20 {
     #[odra(init)]
     pub fn init(&mut self, name: String, symbol: String, decimals: u8, initial_supply: &Balance) {
         let caller = contract_env::caller();
-        self.name.set(name);
-        self.symbol.set(symbol);
-        self.decimals.set(decimals);
-        self.mint(&caller, initial_supply);
+        self.init_embedded(name, symbol, decimals);
+        // the deployer administers the roles and is the first minter
+        self.access_control.unchecked_grant_role(&DEFAULT_ADMIN_ROLE, &caller);
+        self.access_control.unchecked_grant_role(&MINTER_ROLE, &caller);
+        self.raw_mint(&caller, initial_supply);
     }
 
     pub fn name(&self) -> String {
@@ -68,28 +71,32 @@ impl Erc20 {
     }
     
     pub fn mint(&mut self, address: &Address, amount: &Balance) {
-        /*
-            assert caller
-        */
-        self.balances.add(address, *amount);
-        self.total_supply.add(*amount);
-        Transfer {
-            from: None,
-            to: Some(*address),
-            amount: *amount
-        }
-        .emit();
+        self.access_control.check_role(&MINTER_ROLE, &contract_env::caller());
+        self.raw_mint(address, amount);
     }
     pub fn burn(&mut self, owner: &Address, amount: &Balance){
-        /*
-            assert caller
-        */
-        if self.balance_of(owner) < *amount{
-            contract_env::revert(Error::InsufficientBalance);
-        }
-        self.balances.subtract(owner, *amount);
-        self.total_supply.subtract(*amount);
-        // could emit event
+        self.access_control.check_role(&MINTER_ROLE, &contract_env::caller());
+        self.raw_burn(owner, amount);
+    }
+
+    pub fn has_role(&self, role: &Role, account: &Address) -> bool {
+        self.access_control.has_role(role, account)
+    }
+
+    pub fn get_role_admin(&self, role: &Role) -> Role {
+        self.access_control.get_role_admin(role)
+    }
+
+    pub fn grant_role(&mut self, role: &Role, account: &Address) {
+        self.access_control.grant_role(role, account);
+    }
+
+    pub fn revoke_role(&mut self, role: &Role, account: &Address) {
+        self.access_control.revoke_role(role, account);
+    }
+
+    pub fn renounce_role(&mut self, role: &Role, account: &Address) {
+        self.access_control.renounce_role(role, account);
     }
     fn raw_transfer(&mut self, owner: &Address, recipient: &Address, amount: &Balance) {
         let owner_balance = self.balances.get_or_default(&owner);
@@ -124,6 +131,41 @@ impl Erc20 {
     }
 }
 
+// supply changes without caller checks, for modules that embed the token
+impl Erc20 {
+    // metadata only, the embedding module controls the supply and no roles are granted
+    pub(crate) fn init_embedded(&mut self, name: String, symbol: String, decimals: u8) {
+        self.name.set(name);
+        self.symbol.set(symbol);
+        self.decimals.set(decimals);
+    }
+
+    pub(crate) fn raw_mint(&mut self, address: &Address, amount: &Balance) {
+        self.balances.add(address, *amount);
+        self.total_supply.add(*amount);
+        Transfer {
+            from: None,
+            to: Some(*address),
+            amount: *amount
+        }
+        .emit();
+    }
+
+    pub(crate) fn raw_burn(&mut self, owner: &Address, amount: &Balance) {
+        if self.balance_of(owner) < *amount{
+            contract_env::revert(Error::InsufficientBalance);
+        }
+        self.balances.subtract(owner, *amount);
+        self.total_supply.subtract(*amount);
+        Transfer {
+            from: Some(*owner),
+            to: None,
+            amount: *amount
+        }
+        .emit();
+    }
+}
+
 #[derive(Event, PartialEq, Eq, Debug)]
 pub struct Approval {
     pub owner: Address,
@@ -148,6 +190,7 @@ pub struct Transfer {
 #[cfg(test)]
 pub mod tests {
     use super::{Approval, Erc20Deployer, Erc20Ref, Error, Transfer};
+    use crate::access_control::{Error as AccessControlError, RoleGranted, RoleRevoked, DEFAULT_ADMIN_ROLE, MINTER_ROLE};
     use odra::{assert_events, test_env, types::Balance};
 
     pub const NAME: &str = "CasperCoin";
@@ -279,4 +322,102 @@ pub mod tests {
             erc20.transfer_from(&owner, &spender, &amount)
         });
     }
+
+    #[test]
+    fn mint_and_burn_require_minter_role() {
+        let mut erc20 = setup();
+        let (minter, outsider) = (test_env::get_account(0), test_env::get_account(1));
+        let amount = Balance::from(1_000);
+
+        // The deployer holds both roles.
+        assert!(erc20.has_role(&DEFAULT_ADMIN_ROLE, &minter));
+        assert!(erc20.has_role(&MINTER_ROLE, &minter));
+        erc20.mint(&outsider, &amount);
+        erc20.burn(&outsider, &amount);
+        assert_eq!(erc20.total_supply(), INITIAL_SUPPLY.into());
+        assert_events!(
+            erc20,
+            Transfer {
+                from: Some(outsider),
+                to: None,
+                amount
+            }
+        );
+
+        // A minter burning more than the balance hits the Erc20 error.
+        test_env::assert_exception(Error::InsufficientBalance, || {
+            erc20.burn(&outsider, &amount)
+        });
+
+        // Anyone else is rejected by the role check before any balance check.
+        test_env::set_caller(outsider);
+        test_env::assert_exception(AccessControlError::MissingRole, || {
+            erc20.mint(&outsider, &amount)
+        });
+        test_env::assert_exception(AccessControlError::MissingRole, || {
+            erc20.burn(&minter, &amount)
+        });
+        test_env::assert_exception(AccessControlError::MissingRole, || {
+            erc20.burn(&outsider, &amount)
+        });
+    }
+
+    #[test]
+    fn grant_revoke_and_renounce_roles() {
+        let mut erc20 = setup();
+        let (admin, minter) = (test_env::get_account(0), test_env::get_account(1));
+        let amount = Balance::from(1_000);
+
+        // Admin grants the minter role.
+        assert_eq!(erc20.get_role_admin(&MINTER_ROLE), DEFAULT_ADMIN_ROLE);
+        erc20.grant_role(&MINTER_ROLE, &minter);
+        assert!(erc20.has_role(&MINTER_ROLE, &minter));
+        assert_events!(
+            erc20,
+            RoleGranted {
+                role: MINTER_ROLE,
+                account: minter,
+                sender: admin
+            }
+        );
+
+        // The new minter mints but cannot manage roles.
+        test_env::set_caller(minter);
+        erc20.mint(&minter, &amount);
+        assert_eq!(erc20.balance_of(&minter), amount);
+        test_env::assert_exception(AccessControlError::MissingRole, || {
+            erc20.grant_role(&MINTER_ROLE, &test_env::get_account(2))
+        });
+        test_env::assert_exception(AccessControlError::MissingRole, || {
+            erc20.revoke_role(&MINTER_ROLE, &admin)
+        });
+        test_env::assert_exception(AccessControlError::CanOnlyRenounceForSelf, || {
+            erc20.renounce_role(&MINTER_ROLE, &admin)
+        });
+
+        // Admin revokes the minter role.
+        test_env::set_caller(admin);
+        erc20.revoke_role(&MINTER_ROLE, &minter);
+        assert!(!erc20.has_role(&MINTER_ROLE, &minter));
+        assert_events!(
+            erc20,
+            RoleRevoked {
+                role: MINTER_ROLE,
+                account: minter,
+                sender: admin
+            }
+        );
+        test_env::set_caller(minter);
+        test_env::assert_exception(AccessControlError::MissingRole, || {
+            erc20.mint(&minter, &amount)
+        });
+
+        // Admin renounces its own minter role.
+        test_env::set_caller(admin);
+        erc20.renounce_role(&MINTER_ROLE, &admin);
+        assert!(!erc20.has_role(&MINTER_ROLE, &admin));
+        test_env::assert_exception(AccessControlError::MissingRole, || {
+            erc20.mint(&admin, &amount)
+        });
+    }
 }
\ No newline at end of file
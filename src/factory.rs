@@ -0,0 +1,198 @@
+use odra::{Variable, Mapping, contract_env, execution_error, Event};
+use odra::types::Address;
+use odra::types::event::OdraEvent;
+use crate::amm::{AmmContractRef, MAX_FEE};
+
+// canonical list of AmmContract pools, one per token pair. Contracts cannot deploy contracts on
+// Casper, so the admin deploys each pool and `create_pair` checks and records it
+#[odra::module(events = [PairCreated])]
+pub struct AmmFactory {
+    admin: Variable<Address>,
+    pairs: Mapping<Address, Mapping<Address, Address>>,
+    all_pairs: Mapping<u32, Address>,
+    pair_count: Variable<u32>
+}
+
+// swap fee pairs are deployed with, in basis points (0.3%)
+pub const DEFAULT_FEE: u32 = 30;
+
+execution_error! {
+    pub enum Error {
+        IdenticalAddresses => 1,
+        PairExists => 2,
+        NotAdmin => 3,
+        InvalidPair => 4,
+        AdminMismatch => 5,
+        FeeTooHigh => 6,
+    }
+}
+
+#[odra::module]
+impl AmmFactory {
+    #[odra(init)]
+    pub fn init(&mut self) {
+        self.admin.set(contract_env::caller());
+    }
+
+    // records `pair` as the pool of `token_a` and `token_b`, after checking it is an AmmContract
+    // of exactly these tokens, administered by the factory admin and with a fee in range
+    pub fn create_pair(&mut self, token_a: Address, token_b: Address, pair: Address) -> Address {
+        if contract_env::caller() != self.admin.get().unwrap() {
+            contract_env::revert(Error::NotAdmin)
+        }
+        if token_a == token_b {
+            contract_env::revert(Error::IdenticalAddresses)
+        }
+        let (token0, token1) = sort_tokens(token_a, token_b);
+        if self.get_pair(token0, token1).is_some() {
+            contract_env::revert(Error::PairExists)
+        }
+        // the pool has to hold the sorted tokens and be its own LP token
+        let pool: AmmContractRef = AmmContractRef::at(&pair);
+        if pool.token0() != token0 || pool.token1() != token1 || pool.lq_token() != pair {
+            contract_env::revert(Error::InvalidPair)
+        }
+        if pool.admin() != self.admin.get().unwrap() {
+            contract_env::revert(Error::AdminMismatch)
+        }
+        if pool.fee() > MAX_FEE {
+            contract_env::revert(Error::FeeTooHigh)
+        }
+        // register the pair in both directions
+        self.pairs.get_instance(&token0).set(&token1, pair);
+        self.pairs.get_instance(&token1).set(&token0, pair);
+        let index: u32 = self.pair_count.get_or_default();
+        self.all_pairs.set(&index, pair);
+        self.pair_count.set(index + 1);
+        PairCreated {
+            token0,
+            token1,
+            pair,
+            pair_count: index + 1
+        }
+        .emit();
+        pair
+    }
+
+    pub fn admin(&self) -> Address {
+        self.admin.get().unwrap()
+    }
+
+    pub fn get_pair(&self, token_a: Address, token_b: Address) -> Option<Address> {
+        self.pairs.get_instance(&token_a).get(&token_b)
+    }
+
+    pub fn all_pairs(&self, index: u32) -> Option<Address> {
+        self.all_pairs.get(&index)
+    }
+
+    pub fn pair_count(&self) -> u32 {
+        self.pair_count.get_or_default()
+    }
+}
+
+pub fn sort_tokens(token_a: Address, token_b: Address) -> (Address, Address) {
+    if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    }
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct PairCreated {
+    pub token0: Address,
+    pub token1: Address,
+    pub pair: Address,
+    pub pair_count: u32
+}
+
+#[cfg(test)]
+pub mod tests {
+    use odra::{assert_events, test_env};
+    use odra::types::{Address, Balance, OdraError, VmError};
+    use crate::amm::{AmmContractDeployer, AmmContractRef};
+    use crate::erc20::Erc20Deployer;
+    use super::{AmmFactoryDeployer, AmmFactoryRef, Error, PairCreated, sort_tokens, DEFAULT_FEE};
+
+    fn deploy_token(symbol: &str) -> Address {
+        Erc20Deployer::init(symbol.to_string(), symbol.to_string(), 18u8, &Balance::from(0u128)).address().to_owned()
+    }
+
+    // deploys a pool with sorted tokens and records it, the caller has to be the factory admin
+    pub fn create_pair(factory: &mut AmmFactoryRef, token_a: Address, token_b: Address) -> Address {
+        let (token0, token1) = sort_tokens(token_a, token_b);
+        let pair: Address = AmmContractDeployer::init(token0, token1, DEFAULT_FEE, factory.admin()).address().to_owned();
+        factory.create_pair(token_a, token_b, pair)
+    }
+
+    #[test]
+    fn create_pair_records_the_pool() {
+        let mut factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let pair: Address = create_pair(&mut factory, token_a, token_b);
+        let (token0, token1) = sort_tokens(token_a, token_b);
+        // the pair is registered in both directions
+        assert_eq!(factory.get_pair(token_a, token_b), Some(pair));
+        assert_eq!(factory.get_pair(token_b, token_a), Some(pair));
+        assert_eq!(factory.all_pairs(0), Some(pair));
+        assert_eq!(factory.pair_count(), 1);
+        let pool: AmmContractRef = AmmContractRef::at(&pair);
+        assert_eq!(pool.token0(), token0);
+        assert_eq!(pool.token1(), token1);
+        assert_eq!(pool.lq_token(), pair);
+        assert_events!(
+            factory,
+            PairCreated {
+                token0,
+                token1,
+                pair,
+                pair_count: 1
+            }
+        );
+    }
+
+    #[test]
+    fn create_pair_errors() {
+        let mut factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let token_c: Address = deploy_token("TKNC");
+        let (token0, token1) = sort_tokens(token_a, token_b);
+        let admin: Address = factory.admin();
+        let outsider: Address = test_env::get_account(1);
+        let unsorted: Address = AmmContractDeployer::init(token1, token0, DEFAULT_FEE, admin).address().to_owned();
+        let other_tokens: Address = AmmContractDeployer::init(token0, token_c, DEFAULT_FEE, admin).address().to_owned();
+        let other_admin: Address = AmmContractDeployer::init(token0, token1, DEFAULT_FEE, outsider).address().to_owned();
+        let pair: Address = AmmContractDeployer::init(token0, token1, DEFAULT_FEE, admin).address().to_owned();
+        let duplicate: Address = AmmContractDeployer::init(token0, token1, DEFAULT_FEE, admin).address().to_owned();
+        test_env::assert_exception(Error::IdenticalAddresses, || {
+            factory.create_pair(token_a, token_a, pair);
+        });
+        test_env::assert_exception(Error::InvalidPair, || {
+            factory.create_pair(token_a, token_b, unsorted);
+        });
+        test_env::assert_exception(Error::InvalidPair, || {
+            factory.create_pair(token_a, token_b, other_tokens);
+        });
+        // an address that is not a pool cannot answer for its tokens
+        test_env::assert_exception(OdraError::VmError(VmError::NoSuchMethod("token0".to_string())), || {
+            factory.create_pair(token_a, token_b, token_c);
+        });
+        test_env::assert_exception(Error::AdminMismatch, || {
+            factory.create_pair(token_a, token_b, other_admin);
+        });
+        test_env::set_caller(outsider);
+        test_env::assert_exception(Error::NotAdmin, || {
+            factory.create_pair(token_a, token_b, pair);
+        });
+        test_env::set_caller(admin);
+        factory.create_pair(token_b, token_a, pair);
+        test_env::assert_exception(Error::PairExists, || {
+            factory.create_pair(token_a, token_b, duplicate);
+        });
+        assert_eq!(factory.pair_count(), 1);
+        assert_eq!(factory.all_pairs(1), None);
+    }
+}
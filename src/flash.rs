@@ -0,0 +1,14 @@
+use odra::types::{Address, Balance};
+
+// implemented by contracts receiving the output of `AmmContract::flash_swap`
+#[odra::external_contract]
+pub trait FlashSwapCallee {
+    fn on_flash_swap(&mut self, sender: Address, amount0_out: Balance, amount1_out: Balance, data: Vec<u8>);
+}
+
+// implemented by contracts borrowing from `AmmContract::flash_loan`, the loan and the fee
+// have to be transferred back to the pool before the callback returns
+#[odra::external_contract]
+pub trait FlashLoanReceiver {
+    fn on_flash_loan(&mut self, initiator: Address, token: Address, amount: Balance, fee: Balance, data: Vec<u8>);
+}
@@ -1,3 +1,14 @@
+pub mod access_control;
 pub mod amm;
+pub mod concentrated_pool;
+pub mod factory;
+pub mod flash;
+pub mod oracle;
+pub mod reentrancy_guard;
+pub mod router;
+pub mod stable_pool;
+pub mod vault;
+pub mod weighted_pool;
+pub mod wrapped_cspr;
 mod erc20;
 mod math;
\ No newline at end of file
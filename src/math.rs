@@ -1,5 +1,14 @@
+use odra::{contract_env, execution_error};
 use odra::types::{Balance};
 
+// codes start at 400 so they never collide with the errors of the pools using these helpers
+execution_error! {
+    pub enum Error {
+        NonPositiveLogarithm => 400,
+        FixedPointOverflow => 401,
+    }
+}
+
 pub fn _sqrt(y: Balance) -> Balance {
     if y == Balance::from(0) {
         return Balance::from(0);
@@ -19,4 +28,399 @@ pub fn _min(x: Balance, y: Balance) -> Balance {
     } else {
         return y;
     }
-}
\ No newline at end of file
+}
+
+// amount of the other token matching `amount_a` at the current price
+pub fn _quote(amount_a: Balance, reserve_a: Balance, reserve_b: Balance) -> Balance {
+    amount_a * reserve_b / reserve_a
+}
+
+// UQ112x112 fixed point quotient of two balances
+pub fn _uq112_div(numerator: Balance, denominator: Balance) -> Balance {
+    numerator * Balance::from(2u128).pow(Balance::from(112u128)) / denominator
+}
+
+// fees are expressed in basis points
+pub const FEE_DENOMINATOR: u32 = 10000;
+
+// output amount for an exact input, `fee` basis points of the input are kept by the pool
+pub fn _get_amount_out(amount_in: Balance, reserve_in: Balance, reserve_out: Balance, fee: u32) -> Balance {
+    let amount_in_with_fee: Balance = amount_in * Balance::from(FEE_DENOMINATOR - fee);
+    let numerator: Balance = amount_in_with_fee * reserve_out;
+    let denominator: Balance = reserve_in * Balance::from(FEE_DENOMINATOR) + amount_in_with_fee;
+    numerator / denominator
+}
+
+// input amount required for an exact output, rounded up in favour of the pool
+pub fn _get_amount_in(amount_out: Balance, reserve_in: Balance, reserve_out: Balance, fee: u32) -> Balance {
+    let numerator: Balance = reserve_in * amount_out * Balance::from(FEE_DENOMINATOR);
+    let denominator: Balance = (reserve_out - amount_out) * Balance::from(FEE_DENOMINATOR - fee);
+    numerator / denominator + Balance::from(1)
+}
+// number of tokens in a StableSwap pool
+const N_COINS: u32 = 2;
+
+fn _abs_diff(x: Balance, y: Balance) -> Balance {
+    if x > y {
+        x - y
+    } else {
+        y - x
+    }
+}
+
+// StableSwap invariant D of the two balances for the amplification `amp`, by Newton iteration
+pub fn _get_d(x0: Balance, x1: Balance, amp: Balance) -> Balance {
+    let n: Balance = Balance::from(N_COINS);
+    let s: Balance = x0 + x1;
+    if s == Balance::zero() {
+        return Balance::zero();
+    }
+    let ann: Balance = amp * n;
+    let mut d: Balance = s;
+    for _ in 0..255 {
+        let mut d_p: Balance = d;
+        d_p = d_p * d / (x0 * n);
+        d_p = d_p * d / (x1 * n);
+        let d_prev: Balance = d;
+        d = (ann * s + d_p * n) * d / ((ann - Balance::from(1)) * d + (n + Balance::from(1)) * d_p);
+        if _abs_diff(d, d_prev) <= Balance::from(1) {
+            return d;
+        }
+    }
+    d
+}
+
+// balance of the other token keeping the invariant `d` when one token has the balance `x`
+pub fn _get_y(x: Balance, d: Balance, amp: Balance) -> Balance {
+    let n: Balance = Balance::from(N_COINS);
+    let ann: Balance = amp * n;
+    let c: Balance = d * d / (x * n) * d / (ann * n);
+    let b: Balance = x + d / ann;
+    let mut y: Balance = d;
+    for _ in 0..255 {
+        let y_prev: Balance = y;
+        y = (y * y + c) / (Balance::from(2) * y + b - d);
+        if _abs_diff(y, y_prev) <= Balance::from(1) {
+            return y;
+        }
+    }
+    y
+}
+
+// 18 decimal fixed point numbers used by the weighted pool math
+pub const ONE: i128 = 1_000_000_000_000_000_000;
+const LN_2: i128 = 693_147_180_559_945_309;
+// bound of the relative error of `_pow`, results are moved by it in favour of the pool
+const MAX_POW_RELATIVE_ERROR: i128 = 10_000;
+
+// fixed point product, split so `a * b` never has to fit into an i128
+pub fn _mul_fixed(a: i128, b: i128) -> i128 {
+    a * (b / ONE) + a * (b % ONE) / ONE
+}
+
+// natural logarithm of a positive fixed point number
+pub fn _ln(x: i128) -> i128 {
+    if x <= 0 {
+        contract_env::revert(Error::NonPositiveLogarithm)
+    }
+    // x = m * 2^k with m in [1, 2)
+    let mut k: i128 = 0;
+    let mut m: i128 = x;
+    while m >= 2 * ONE {
+        m /= 2;
+        k += 1;
+    }
+    while m < ONE {
+        m *= 2;
+        k -= 1;
+    }
+    // ln(m) = 2 * atanh(z) with z = (m - 1) / (m + 1) <= 1/3
+    let z: i128 = (m - ONE) * ONE / (m + ONE);
+    let z_squared: i128 = z * z / ONE;
+    let mut term: i128 = z;
+    let mut sum: i128 = 0;
+    let mut i: i128 = 1;
+    while term != 0 {
+        sum += term / i;
+        term = term * z_squared / ONE;
+        i += 2;
+    }
+    k * LN_2 + 2 * sum
+}
+
+// exponential of a fixed point number
+pub fn _exp(x: i128) -> i128 {
+    // x = k * ln(2) + r with r in [0, ln(2))
+    let k: i128 = x.div_euclid(LN_2);
+    let r: i128 = x - k * LN_2;
+    if k < -64 {
+        return 0;
+    }
+    // the series sum stays below 2 * ONE, so shifting it further would not fit into an i128
+    if k > 64 {
+        contract_env::revert(Error::FixedPointOverflow)
+    }
+    let mut sum: i128 = ONE;
+    let mut term: i128 = ONE;
+    let mut i: i128 = 1;
+    loop {
+        term = term * r / ONE / i;
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        i += 1;
+    }
+    if k >= 0 {
+        sum << k as u32
+    } else {
+        sum >> (-k) as u32
+    }
+}
+
+// `x` to the power of `y` for a positive fixed point `x`
+pub fn _pow(x: i128, y: i128) -> i128 {
+    _exp(_mul_fixed(_ln(x), y))
+}
+
+// balance as a fixed point number, reverting if it does not fit into an i128
+fn _to_fixed(x: Balance) -> i128 {
+    if x > Balance::from(i128::MAX as u128) {
+        contract_env::revert(Error::FixedPointOverflow)
+    }
+    x.as_u128() as i128
+}
+
+// non-negative fixed point number as a balance
+fn _from_fixed(x: i128) -> Balance {
+    match u128::try_from(x) {
+        Ok(x) => Balance::from(x),
+        Err(_) => contract_env::revert(Error::FixedPointOverflow)
+    }
+}
+
+// output amount of a weighted pool for an exact input after fees, rounded down
+pub fn _weighted_amount_out(balance_in: Balance, weight_in: u32, balance_out: Balance, weight_out: u32, amount_in: Balance) -> Balance {
+    let base: i128 = _to_fixed(balance_in * _from_fixed(ONE) / (balance_in + amount_in));
+    let exponent: i128 = weight_in as i128 * ONE / weight_out as i128;
+    let power: i128 = _pow(base, exponent);
+    let power_up: i128 = power + power * MAX_POW_RELATIVE_ERROR / ONE + 1;
+    if power_up >= ONE {
+        return Balance::zero();
+    }
+    balance_out * _from_fixed(ONE - power_up) / _from_fixed(ONE)
+}
+
+// input amount of a weighted pool for an exact output before fees, rounded up
+pub fn _weighted_amount_in(balance_in: Balance, weight_in: u32, balance_out: Balance, weight_out: u32, amount_out: Balance) -> Balance {
+    let remaining: Balance = balance_out - amount_out;
+    let base: i128 = _to_fixed((balance_out * _from_fixed(ONE) + remaining - Balance::from(1)) / remaining);
+    let exponent: i128 = weight_out as i128 * ONE / weight_in as i128;
+    let power: i128 = _pow(base, exponent);
+    let power_up: i128 = power + power * MAX_POW_RELATIVE_ERROR / ONE + 1;
+    balance_in * _from_fixed(power_up - ONE) / _from_fixed(ONE) + Balance::from(1)
+}
+
+// bounds of the ticks of a concentrated liquidity pool, the price at tick i is 1.0001^i
+pub const MIN_TICK: i32 = -887272;
+pub const MAX_TICK: i32 = 887272;
+// Q64.96 square root prices at MIN_TICK and MAX_TICK
+pub const MIN_SQRT_RATIO: u128 = 4295128739;
+const MAX_SQRT_RATIO: &str = "1461446703485210103287273052203988822378723970342";
+
+// 1 / sqrt(1.0001)^(2^i) as Q128.128 numbers for every bit of a tick
+const TICK_RATIOS: [u128; 20] = [
+    0xfffcb933bd6fad37aa2d162d1a594001,
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2
+];
+
+pub fn _max_sqrt_ratio() -> Balance {
+    Balance::from_dec_str(MAX_SQRT_RATIO).unwrap()
+}
+
+fn _div_rounding_up(numerator: Balance, denominator: Balance) -> Balance {
+    (numerator + denominator - Balance::from(1)) / denominator
+}
+
+// fee growth accumulators wrap around modulo 2^256
+pub fn _add_mod_256(x: Balance, y: Balance) -> Balance {
+    (x + y) % (Balance::from(1) << 256)
+}
+
+pub fn _sub_mod_256(x: Balance, y: Balance) -> Balance {
+    (x + (Balance::from(1) << 256) - y) % (Balance::from(1) << 256)
+}
+
+// Q64.96 square root price at `tick`, rounded up
+pub fn _get_sqrt_ratio_at_tick(tick: i32) -> Balance {
+    let abs_tick: u32 = tick.unsigned_abs();
+    let mut ratio: Balance = Balance::from(1) << 128;
+    for (i, tick_ratio) in TICK_RATIOS.iter().enumerate() {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * Balance::from(*tick_ratio)) >> 128;
+        }
+    }
+    // the ratios above are for negative ticks
+    if tick > 0 {
+        ratio = ((Balance::from(1) << 256) - Balance::from(1)) / ratio;
+    }
+    _div_rounding_up(ratio, Balance::from(1) << 32)
+}
+
+// greatest tick whose square root price is at most `sqrt_price_x96`, by binary search
+pub fn _get_tick_at_sqrt_ratio(sqrt_price_x96: Balance) -> i32 {
+    let mut low: i32 = MIN_TICK;
+    let mut high: i32 = MAX_TICK;
+    while low < high {
+        let mid: i32 = (low + high + 1).div_euclid(2);
+        if _get_sqrt_ratio_at_tick(mid) <= sqrt_price_x96 {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
+// amount of token0 between two square root prices for `liquidity`
+pub fn _get_amount0_delta(sqrt_ratio_a: Balance, sqrt_ratio_b: Balance, liquidity: Balance, round_up: bool) -> Balance {
+    let (lower, upper) = if sqrt_ratio_a < sqrt_ratio_b { (sqrt_ratio_a, sqrt_ratio_b) } else { (sqrt_ratio_b, sqrt_ratio_a) };
+    let numerator: Balance = (liquidity << 96) * (upper - lower);
+    if round_up {
+        _div_rounding_up(_div_rounding_up(numerator, upper), lower)
+    } else {
+        numerator / upper / lower
+    }
+}
+
+// amount of token1 between two square root prices for `liquidity`
+pub fn _get_amount1_delta(sqrt_ratio_a: Balance, sqrt_ratio_b: Balance, liquidity: Balance, round_up: bool) -> Balance {
+    let (lower, upper) = if sqrt_ratio_a < sqrt_ratio_b { (sqrt_ratio_a, sqrt_ratio_b) } else { (sqrt_ratio_b, sqrt_ratio_a) };
+    if round_up {
+        _div_rounding_up(liquidity * (upper - lower), Balance::from(1) << 96)
+    } else {
+        (liquidity * (upper - lower)) >> 96
+    }
+}
+
+// square root price after adding `amount_in`, rounded so the pool never receives too little
+pub fn _get_next_sqrt_price_from_input(sqrt_price_x96: Balance, liquidity: Balance, amount_in: Balance, zero_for_one: bool) -> Balance {
+    if zero_for_one {
+        let numerator: Balance = liquidity << 96;
+        _div_rounding_up(numerator * sqrt_price_x96, numerator + amount_in * sqrt_price_x96)
+    } else {
+        sqrt_price_x96 + (amount_in << 96) / liquidity
+    }
+}
+
+// one step of an exact input swap towards `sqrt_price_target_x96`, `fee` basis points of the input are kept
+// returns the new square root price, the input without the fee, the output and the fee
+pub fn _compute_swap_step(sqrt_price_x96: Balance, sqrt_price_target_x96: Balance, liquidity: Balance, amount_remaining: Balance, fee: u32) -> (Balance, Balance, Balance, Balance) {
+    let zero_for_one: bool = sqrt_price_x96 >= sqrt_price_target_x96;
+    let amount_remaining_less_fee: Balance = amount_remaining * Balance::from(FEE_DENOMINATOR - fee) / Balance::from(FEE_DENOMINATOR);
+    let mut amount_in: Balance = if zero_for_one {
+        _get_amount0_delta(sqrt_price_target_x96, sqrt_price_x96, liquidity, true)
+    } else {
+        _get_amount1_delta(sqrt_price_x96, sqrt_price_target_x96, liquidity, true)
+    };
+    let sqrt_price_next_x96: Balance = if amount_remaining_less_fee >= amount_in {
+        sqrt_price_target_x96
+    } else {
+        _get_next_sqrt_price_from_input(sqrt_price_x96, liquidity, amount_remaining_less_fee, zero_for_one)
+    };
+    let max: bool = sqrt_price_next_x96 == sqrt_price_target_x96;
+    if !max {
+        amount_in = if zero_for_one {
+            _get_amount0_delta(sqrt_price_next_x96, sqrt_price_x96, liquidity, true)
+        } else {
+            _get_amount1_delta(sqrt_price_x96, sqrt_price_next_x96, liquidity, true)
+        };
+    }
+    let amount_out: Balance = if zero_for_one {
+        _get_amount1_delta(sqrt_price_next_x96, sqrt_price_x96, liquidity, false)
+    } else {
+        _get_amount0_delta(sqrt_price_x96, sqrt_price_next_x96, liquidity, false)
+    };
+    // the rest of the input is the fee once the target is not reached
+    let fee_amount: Balance = if max {
+        _div_rounding_up(amount_in * Balance::from(fee), Balance::from(FEE_DENOMINATOR - fee))
+    } else {
+        amount_remaining - amount_in
+    };
+    (sqrt_price_next_x96, amount_in, amount_out, fee_amount)
+}
+
+#[cfg(test)]
+mod tests {
+    use odra::test_env;
+    use super::*;
+
+    const E: i128 = 2_718_281_828_459_045_235;
+
+    fn assert_close(actual: i128, expected: i128, tolerance: i128) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn ln(){
+        assert_eq!(_ln(ONE), 0);
+        assert_close(_ln(E), ONE, 100);
+        assert_close(_ln(ONE / 2), -LN_2, 100);
+        assert_close(_ln(8 * ONE), 3 * LN_2, 100);
+    }
+
+    #[test]
+    fn exp(){
+        assert_eq!(_exp(0), ONE);
+        assert_close(_exp(ONE), E, 100);
+        assert_close(_exp(-ONE), 367_879_441_171_442_321, 100);
+        assert_eq!(_exp(-100 * ONE), 0);
+    }
+
+    #[test]
+    fn pow(){
+        assert_close(_pow(3 * ONE, ONE), 3 * ONE, 1_000);
+        assert_close(_pow(ONE / 3, ONE), ONE / 3, 1_000);
+        // fractional exponents
+        assert_close(_pow(4 * ONE, ONE / 2), 2 * ONE, 1_000);
+        assert_close(_pow(3 * ONE / 2, ONE / 4), 1_106_681_919_700_321_592, 1_000);
+        // negative exponents
+        assert_close(_pow(2 * ONE, -ONE), ONE / 2, 1_000);
+        assert_close(_pow(10 * ONE, -ONE / 2), 316_227_766_016_837_933, 1_000);
+    }
+
+    #[test]
+    fn ln_of_non_positive(){
+        test_env::assert_exception(Error::NonPositiveLogarithm, || {
+            _ln(0);
+        });
+        test_env::assert_exception(Error::NonPositiveLogarithm, || {
+            _ln(-ONE);
+        });
+    }
+
+    #[test]
+    fn exp_overflow(){
+        test_env::assert_exception(Error::FixedPointOverflow, || {
+            _exp(100 * ONE);
+        });
+    }
+}
@@ -0,0 +1,179 @@
+use odra::{Variable, Mapping, contract_env, execution_error, OdraType};
+use odra::types::Balance;
+
+#[derive(OdraType, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Observation {
+    pub timestamp: u64,
+    pub price0_cumulative: Balance,
+    pub price1_cumulative: Balance,
+    pub liquidity_cumulative: Balance,
+    pub initialized: bool
+}
+
+// ring buffer of price and liquidity observations, written by the pool on every reserve update
+#[odra::module]
+pub struct Oracle {
+    observations: Mapping<u32, Observation>,
+    index: Variable<u32>,
+    cardinality: Variable<u32>,
+    cardinality_next: Variable<u32>
+}
+
+// largest ring buffer a pool can be grown to, as in Uniswap V3
+pub const MAX_CARDINALITY: u32 = 65535;
+
+// codes start at 100 so they never collide with the errors of the owning module
+execution_error! {
+    pub enum Error {
+        OldObservation => 100,
+        CardinalityTooHigh => 101,
+    }
+}
+
+impl Oracle {
+    pub fn initialize(&mut self, timestamp: u64) {
+        self.observations.set(&0, Observation {
+            timestamp,
+            price0_cumulative: Balance::zero(),
+            price1_cumulative: Balance::zero(),
+            liquidity_cumulative: Balance::zero(),
+            initialized: true
+        });
+        self.index.set(0);
+        self.cardinality.set(1);
+        self.cardinality_next.set(1);
+    }
+
+    // appends an observation, `liquidity` is the liquidity since the last observation
+    pub fn write(&mut self, timestamp: u64, price0_cumulative: Balance, price1_cumulative: Balance, liquidity: Balance) {
+        let index: u32 = self.index.get_or_default();
+        let last: Observation = self.observations.get_or_default(&index);
+        if last.timestamp == timestamp {
+            return;
+        }
+        // the buffer only grows once the newest slot is the last one
+        let mut cardinality: u32 = self.cardinality.get_or_default();
+        let cardinality_next: u32 = self.cardinality_next.get_or_default();
+        if cardinality_next > cardinality && index == cardinality - 1 {
+            cardinality = cardinality_next;
+            self.cardinality.set(cardinality);
+        }
+        let index_updated: u32 = (index + 1) % cardinality;
+        self.observations.set(&index_updated, Observation {
+            timestamp,
+            price0_cumulative,
+            price1_cumulative,
+            liquidity_cumulative: last.liquidity_cumulative + liquidity * Balance::from(timestamp - last.timestamp),
+            initialized: true
+        });
+        self.index.set(index_updated);
+    }
+
+    pub fn grow(&mut self, next: u32) {
+        if next > MAX_CARDINALITY {
+            contract_env::revert(Error::CardinalityTooHigh)
+        }
+        if next > self.cardinality_next.get_or_default() {
+            self.cardinality_next.set(next);
+        }
+    }
+
+    // `current` is the counterfactual observation at `now`
+    pub fn observe(&self, now: u64, seconds_ago: Vec<u64>, current: Observation) -> Vec<Observation> {
+        seconds_ago
+            .iter()
+            .map(|ago| match now.checked_sub(*ago) {
+                Some(target) => self.observe_single(target, current),
+                None => contract_env::revert(Error::OldObservation)
+            })
+            .collect()
+    }
+
+    pub fn observation(&self, index: u32) -> Option<Observation> {
+        self.observations.get(&index)
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index.get_or_default()
+    }
+
+    pub fn cardinality(&self) -> u32 {
+        self.cardinality.get_or_default()
+    }
+
+    pub fn cardinality_next(&self) -> u32 {
+        self.cardinality_next.get_or_default()
+    }
+
+    fn observe_single(&self, target: u64, current: Observation) -> Observation {
+        let index: u32 = self.index.get_or_default();
+        let cardinality: u32 = self.cardinality.get_or_default();
+        let newest: Observation = self.observations.get_or_default(&index);
+        if target >= newest.timestamp {
+            if target == newest.timestamp {
+                return newest;
+            }
+            return interpolate(&newest, &current, target);
+        }
+        // the slot after the newest one holds the oldest observation once the buffer is full
+        let mut oldest: Observation = self.observations.get_or_default(&((index + 1) % cardinality));
+        if !oldest.initialized {
+            oldest = self.observations.get_or_default(&0);
+        }
+        if target < oldest.timestamp {
+            contract_env::revert(Error::OldObservation)
+        }
+        let (before, after) = self.binary_search(target, index, cardinality);
+        if target == before.timestamp {
+            return before;
+        }
+        if target == after.timestamp {
+            return after;
+        }
+        interpolate(&before, &after, target)
+    }
+
+    // finds the observations surrounding `target`, assuming it lies within the buffer
+    // the search runs on u64 positions so `l + r` cannot overflow, slots are taken modulo `cardinality`
+    fn binary_search(&self, target: u64, index: u32, cardinality: u32) -> (Observation, Observation) {
+        let cardinality: u64 = u64::from(cardinality);
+        let mut l: u64 = (u64::from(index) + 1) % cardinality;
+        let mut r: u64 = l + cardinality - 1;
+        loop {
+            let i: u64 = (l + r) / 2;
+            let before: Observation = self.observations.get_or_default(&slot(i, cardinality));
+            if !before.initialized {
+                l = i + 1;
+                continue;
+            }
+            let after: Observation = self.observations.get_or_default(&slot(i + 1, cardinality));
+            let target_at_or_after: bool = before.timestamp <= target;
+            if target_at_or_after && target <= after.timestamp {
+                return (before, after);
+            }
+            if !target_at_or_after {
+                r = i - 1;
+            } else {
+                l = i + 1;
+            }
+        }
+    }
+}
+
+// ring buffer slot of the search position `i`
+fn slot(i: u64, cardinality: u64) -> u32 {
+    (i % cardinality) as u32
+}
+
+// linear interpolation of the cumulative values between two observations
+fn interpolate(before: &Observation, after: &Observation, target: u64) -> Observation {
+    let elapsed: Balance = Balance::from(target - before.timestamp);
+    let delta: Balance = Balance::from(after.timestamp - before.timestamp);
+    Observation {
+        timestamp: target,
+        price0_cumulative: before.price0_cumulative + (after.price0_cumulative - before.price0_cumulative) * elapsed / delta,
+        price1_cumulative: before.price1_cumulative + (after.price1_cumulative - before.price1_cumulative) * elapsed / delta,
+        liquidity_cumulative: before.liquidity_cumulative + (after.liquidity_cumulative - before.liquidity_cumulative) * elapsed / delta,
+        initialized: true
+    }
+}
@@ -0,0 +1,27 @@
+use odra::{Variable, contract_env, execution_error};
+
+// reverts nested calls into the entry points of the module owning the guard
+#[odra::module]
+pub struct ReentrancyGuard {
+    locked: Variable<bool>
+}
+
+// codes start at 200 so they never collide with the errors of the owning module
+execution_error! {
+    pub enum Error {
+        Locked => 200,
+    }
+}
+
+impl ReentrancyGuard {
+    pub fn lock(&mut self) {
+        if self.locked.get_or_default() {
+            contract_env::revert(Error::Locked)
+        }
+        self.locked.set(true);
+    }
+
+    pub fn unlock(&mut self) {
+        self.locked.set(false);
+    }
+}
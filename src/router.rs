@@ -0,0 +1,387 @@
+use odra::{Variable, contract_env, execution_error};
+use odra::types::{Address, Balance};
+use crate::amm::AmmContractRef;
+use crate::erc20::Erc20Ref;
+use crate::factory::AmmFactoryRef;
+use crate::wrapped_cspr::WrappedCsprRef;
+
+#[odra::module]
+pub struct Router {
+    factory: Variable<Address>,
+    wcspr: Variable<Address>
+}
+
+execution_error! {
+    pub enum Error {
+        InvalidPath => 1,
+        PairNotFound => 2,
+        InsufficientOutputAmount => 3,
+        Expired => 4,
+    }
+}
+
+#[odra::module]
+impl Router {
+    #[odra(init)]
+    pub fn init(&mut self, factory: Address, wcspr: Address) {
+        self.factory.set(factory);
+        self.wcspr.set(wcspr);
+    }
+
+    pub fn swap_exact_tokens_for_tokens(&mut self, amount_in: Balance, min_amount_out: Balance, path: Vec<Address>, deadline: u64) -> Balance {
+        ensure(deadline);
+        if path.len() < 2 {
+            contract_env::revert(Error::InvalidPath)
+        }
+        let caller: Address = contract_env::caller();
+        // pull the input once, every hop is paid by the router
+        Erc20Ref::at(&path[0]).transfer_from(&caller, &contract_env::self_address(), &amount_in);
+        let amount: Balance = self.swap_path(amount_in, &path);
+        // the slippage bound applies to the output of the whole path
+        if amount < min_amount_out {
+            contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        // deliver the final token to the caller
+        Erc20Ref::at(&path[path.len() - 1]).transfer(&caller, &amount);
+        amount
+    }
+
+    // wraps the attached CSPR and swaps it along a path starting with WCSPR
+    #[odra(payable)]
+    pub fn swap_exact_cspr_for_tokens(&mut self, min_amount_out: Balance, path: Vec<Address>, deadline: u64) -> Balance {
+        ensure(deadline);
+        let wcspr: Address = self.wcspr.get().unwrap();
+        if path.len() < 2 || path[0] != wcspr {
+            contract_env::revert(Error::InvalidPath)
+        }
+        let amount_in: Balance = contract_env::attached_value();
+        WrappedCsprRef::at(&wcspr).with_tokens(amount_in).deposit();
+        let amount: Balance = self.swap_path(amount_in, &path);
+        if amount < min_amount_out {
+            contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        Erc20Ref::at(&path[path.len() - 1]).transfer(&contract_env::caller(), &amount);
+        amount
+    }
+
+    // swaps along a path ending with WCSPR and sends the unwrapped CSPR to the caller
+    pub fn swap_exact_tokens_for_cspr(&mut self, amount_in: Balance, min_amount_out: Balance, path: Vec<Address>, deadline: u64) -> Balance {
+        ensure(deadline);
+        let wcspr: Address = self.wcspr.get().unwrap();
+        if path.len() < 2 || path[path.len() - 1] != wcspr {
+            contract_env::revert(Error::InvalidPath)
+        }
+        let caller: Address = contract_env::caller();
+        Erc20Ref::at(&path[0]).transfer_from(&caller, &contract_env::self_address(), &amount_in);
+        let amount: Balance = self.swap_path(amount_in, &path);
+        if amount < min_amount_out {
+            contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        WrappedCsprRef::at(&wcspr).withdraw_to(caller, amount);
+        amount
+    }
+
+    // adds liquidity to the `token`/WCSPR pair with the attached CSPR, the unused part of both sides is refunded
+    #[odra(payable)]
+    pub fn add_liquidity_cspr(&mut self, token: Address, amount_token_desired: Balance, amount_token_min: Balance, amount_cspr_min: Balance, deadline: u64) -> Balance {
+        ensure(deadline);
+        let caller: Address = contract_env::caller();
+        let router: Address = contract_env::self_address();
+        let wcspr: Address = self.wcspr.get().unwrap();
+        let pair: Address = self.pair_for(token, wcspr);
+        let amount_cspr_desired: Balance = contract_env::attached_value();
+        Erc20Ref::at(&token).transfer_from(&caller, &router, &amount_token_desired);
+        WrappedCsprRef::at(&wcspr).with_tokens(amount_cspr_desired).deposit();
+        Erc20Ref::at(&token).approve(&pair, &amount_token_desired);
+        Erc20Ref::at(&wcspr).approve(&pair, &amount_cspr_desired);
+        let mut pool: AmmContractRef = AmmContractRef::at(&pair);
+        let token_before: Balance = Erc20Ref::at(&token).balance_of(&router);
+        let cspr_before: Balance = Erc20Ref::at(&wcspr).balance_of(&router);
+        let shares: Balance = if pool.token0() == token {
+            pool.add_liquidity(amount_token_desired, amount_cspr_desired, amount_token_min, amount_cspr_min)
+        } else {
+            pool.add_liquidity(amount_cspr_desired, amount_token_desired, amount_cspr_min, amount_token_min)
+        };
+        let token_used: Balance = token_before - Erc20Ref::at(&token).balance_of(&router);
+        let cspr_used: Balance = cspr_before - Erc20Ref::at(&wcspr).balance_of(&router);
+        // no allowance outlives the call
+        Erc20Ref::at(&token).approve(&pair, &Balance::zero());
+        Erc20Ref::at(&wcspr).approve(&pair, &Balance::zero());
+        Erc20Ref::at(&pool.lq_token()).transfer(&caller, &shares);
+        // refund only what the caller brought and the pool did not take
+        if amount_token_desired > token_used {
+            Erc20Ref::at(&token).transfer(&caller, &(amount_token_desired - token_used));
+        }
+        if amount_cspr_desired > cspr_used {
+            WrappedCsprRef::at(&wcspr).withdraw_to(caller, amount_cspr_desired - cspr_used);
+        }
+        shares
+    }
+
+    // removes liquidity from the `token`/WCSPR pair, the LP shares have to be approved to the router
+    pub fn remove_liquidity_cspr(&mut self, token: Address, shares: Balance, amount_token_min: Balance, amount_cspr_min: Balance, deadline: u64) -> (Balance, Balance) {
+        let caller: Address = contract_env::caller();
+        let wcspr: Address = self.wcspr.get().unwrap();
+        let pair: Address = self.pair_for(token, wcspr);
+        let mut pool: AmmContractRef = AmmContractRef::at(&pair);
+        Erc20Ref::at(&pool.lq_token()).transfer_from(&caller, &contract_env::self_address(), &shares);
+        let (amount_token, amount_cspr) = if pool.token0() == token {
+            pool.remove_liquidity(shares, amount_token_min, amount_cspr_min, deadline)
+        } else {
+            let (amount0, amount1) = pool.remove_liquidity(shares, amount_cspr_min, amount_token_min, deadline);
+            (amount1, amount0)
+        };
+        Erc20Ref::at(&token).transfer(&caller, &amount_token);
+        WrappedCsprRef::at(&wcspr).withdraw_to(caller, amount_cspr);
+        (amount_token, amount_cspr)
+    }
+
+    pub fn factory(&self) -> Address {
+        self.factory.get().unwrap()
+    }
+
+    pub fn wcspr(&self) -> Address {
+        self.wcspr.get().unwrap()
+    }
+
+    // swaps `amount_in` held by the router through every pair of the path
+    fn swap_path(&self, amount_in: Balance, path: &[Address]) -> Balance {
+        let mut amount: Balance = amount_in;
+        for hop in path.windows(2) {
+            let pair: Address = self.pair_for(hop[0], hop[1]);
+            Erc20Ref::at(&hop[0]).approve(&pair, &amount);
+            amount = AmmContractRef::at(&pair).swap(amount, hop[0]);
+        }
+        amount
+    }
+
+    fn pair_for(&self, token_a: Address, token_b: Address) -> Address {
+        match AmmFactoryRef::at(&self.factory.get().unwrap()).get_pair(token_a, token_b) {
+            Some(pair) => pair,
+            None => contract_env::revert(Error::PairNotFound)
+        }
+    }
+}
+
+fn ensure(deadline: u64) {
+    if contract_env::get_block_time() > deadline {
+        contract_env::revert(Error::Expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use odra::test_env;
+    use odra::types::{Address, Balance};
+    use crate::amm::AmmContractRef;
+    use crate::erc20::{Erc20Deployer, Erc20Ref};
+    use crate::factory::{AmmFactoryDeployer, AmmFactoryRef};
+    use crate::factory::tests::create_pair;
+    use crate::wrapped_cspr::WrappedCsprDeployer;
+    use super::{Error, RouterDeployer, RouterRef};
+
+    fn deploy_token(symbol: &str) -> Address {
+        Erc20Deployer::init(symbol.to_string(), symbol.to_string(), 18u8, &Balance::from(0u128)).address().to_owned()
+    }
+
+    fn seed_pair(factory: &mut AmmFactoryRef, token_a: Address, token_b: Address, amount: Balance) -> Address {
+        let provider: Address = test_env::get_account(2);
+        let pair: Address = create_pair(factory, token_a, token_b);
+        let pool: AmmContractRef = AmmContractRef::at(&pair);
+        Erc20Ref::at(&pool.token0()).mint(&provider, &amount);
+        Erc20Ref::at(&pool.token1()).mint(&provider, &amount);
+        test_env::set_caller(provider);
+        Erc20Ref::at(&pool.token0()).approve(&pair, &amount);
+        Erc20Ref::at(&pool.token1()).approve(&pair, &amount);
+        AmmContractRef::at(&pair).add_liquidity(amount, amount, Balance::zero(), Balance::zero());
+        test_env::set_caller(test_env::get_account(0));
+        pair
+    }
+
+    // the provider seeds the `token`/WCSPR pair with native CSPR through the router
+    fn seed_cspr_pair(factory: &mut AmmFactoryRef, router: &mut RouterRef, token: Address, amount: Balance) -> Address {
+        let provider: Address = test_env::get_account(2);
+        let pair: Address = create_pair(factory, token, router.wcspr());
+        Erc20Ref::at(&token).mint(&provider, &amount);
+        test_env::set_caller(provider);
+        Erc20Ref::at(&token).approve(router.address(), &amount);
+        router.with_tokens(amount).add_liquidity_cspr(token, amount, Balance::zero(), Balance::zero(), u64::MAX);
+        test_env::set_caller(test_env::get_account(0));
+        pair
+    }
+
+    #[test]
+    fn multi_hop_swap() {
+        let user: Address = test_env::get_account(1);
+        let mut factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let mut router: RouterRef = RouterDeployer::init(factory.address().to_owned(), WrappedCsprDeployer::init().address().to_owned());
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let token_c: Address = deploy_token("TKNC");
+        seed_pair(&mut factory, token_a, token_b, Balance::from(5000u128));
+        seed_pair(&mut factory, token_b, token_c, Balance::from(5000u128));
+        // fund the user and approve the router once
+        Erc20Ref::at(&token_a).mint(&user, &Balance::from(1000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token_a).approve(router.address(), &Balance::from(1000u128));
+        // swap A -> B -> C in a single call
+        let amount_out: Balance = router.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::from(710u128), vec![token_a, token_b, token_c], u64::MAX);
+        // 1000 A -> 831 B -> 710 C
+        assert_eq!(amount_out, Balance::from(710u128));
+        assert_eq!(Erc20Ref::at(&token_c).balance_of(&user), Balance::from(710u128));
+        assert_eq!(Erc20Ref::at(&token_a).balance_of(&user), Balance::zero());
+    }
+
+    #[test]
+    fn swap_slippage_and_deadline() {
+        let user: Address = test_env::get_account(1);
+        let mut factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let mut router: RouterRef = RouterDeployer::init(factory.address().to_owned(), WrappedCsprDeployer::init().address().to_owned());
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let token_c: Address = deploy_token("TKNC");
+        seed_pair(&mut factory, token_a, token_b, Balance::from(5000u128));
+        seed_pair(&mut factory, token_b, token_c, Balance::from(5000u128));
+        Erc20Ref::at(&token_a).mint(&user, &Balance::from(1000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token_a).approve(router.address(), &Balance::from(1000u128));
+        // the path only yields 710 C
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            router.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::from(711u128), vec![token_a, token_b, token_c], u64::MAX);
+        });
+        // the transaction is included after the deadline
+        test_env::advance_block_time_by(100);
+        test_env::assert_exception(Error::Expired, || {
+            router.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::zero(), vec![token_a, token_b, token_c], 99);
+        });
+        assert_eq!(Erc20Ref::at(&token_a).balance_of(&user), Balance::from(1000u128));
+    }
+
+    #[test]
+    fn invalid_paths() {
+        let factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let mut router: RouterRef = RouterDeployer::init(factory.address().to_owned(), WrappedCsprDeployer::init().address().to_owned());
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        test_env::assert_exception(Error::InvalidPath, || {
+            router.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::zero(), vec![token_a], u64::MAX);
+        });
+        Erc20Ref::at(&token_a).mint(&test_env::get_account(0), &Balance::from(1000u128));
+        Erc20Ref::at(&token_a).approve(router.address(), &Balance::from(1000u128));
+        test_env::assert_exception(Error::PairNotFound, || {
+            router.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::zero(), vec![token_a, token_b], u64::MAX);
+        });
+    }
+
+    #[test]
+    fn cspr_swaps_and_liquidity() {
+        let provider: Address = test_env::get_account(2);
+        let user: Address = test_env::get_account(1);
+        let mut factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let wcspr: Address = WrappedCsprDeployer::init().address().to_owned();
+        let mut router: RouterRef = RouterDeployer::init(factory.address().to_owned(), wcspr);
+        let token: Address = deploy_token("TKNA");
+        let pair: Address = create_pair(&mut factory, token, wcspr);
+        // the provider seeds the pair with native CSPR
+        Erc20Ref::at(&token).mint(&provider, &Balance::from(5000u128));
+        test_env::set_caller(provider);
+        Erc20Ref::at(&token).approve(router.address(), &Balance::from(5000u128));
+        let shares: Balance = router.with_tokens(Balance::from(5000u128)).add_liquidity_cspr(token, Balance::from(5000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(shares, Balance::from(4000u128));
+        let lq_token: Address = AmmContractRef::at(&pair).lq_token();
+        assert_eq!(Erc20Ref::at(&lq_token).balance_of(&provider), shares);
+        assert_eq!(Erc20Ref::at(&wcspr).balance_of(&pair), Balance::from(5000u128));
+        // CSPR -> token
+        test_env::set_caller(user);
+        let amount_out: Balance = router.with_tokens(Balance::from(1000u128)).swap_exact_cspr_for_tokens(Balance::from(831u128), vec![wcspr, token], u64::MAX);
+        assert_eq!(amount_out, Balance::from(831u128));
+        assert_eq!(Erc20Ref::at(&token).balance_of(&user), Balance::from(831u128));
+        // token -> CSPR
+        let before: Balance = test_env::token_balance(user);
+        Erc20Ref::at(&token).approve(router.address(), &Balance::from(831u128));
+        let cspr_out: Balance = router.swap_exact_tokens_for_cspr(Balance::from(831u128), Balance::from(994u128), vec![token, wcspr], u64::MAX);
+        assert_eq!(cspr_out, Balance::from(994u128));
+        assert_eq!(test_env::token_balance(user), before + cspr_out);
+        assert_eq!(Erc20Ref::at(&wcspr).balance_of(router.address()), Balance::zero());
+        // the provider takes out liquidity as CSPR
+        test_env::set_caller(provider);
+        let before: Balance = test_env::token_balance(provider);
+        Erc20Ref::at(&lq_token).approve(router.address(), &Balance::from(2000u128));
+        let amounts: (Balance, Balance) = router.remove_liquidity_cspr(token, Balance::from(2000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(amounts, (Balance::from(2000u128), Balance::from(2002u128)));
+        assert_eq!(Erc20Ref::at(&token).balance_of(&provider), Balance::from(2000u128));
+        assert_eq!(test_env::token_balance(provider), before + Balance::from(2002u128));
+    }
+
+    #[test]
+    fn cspr_invalid_paths() {
+        let factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let wcspr: Address = WrappedCsprDeployer::init().address().to_owned();
+        let mut router: RouterRef = RouterDeployer::init(factory.address().to_owned(), wcspr);
+        let token: Address = deploy_token("TKNA");
+        test_env::assert_exception(Error::InvalidPath, || {
+            router.with_tokens(Balance::from(1000u128)).swap_exact_cspr_for_tokens(Balance::zero(), vec![token, wcspr], u64::MAX);
+        });
+        test_env::assert_exception(Error::InvalidPath, || {
+            router.swap_exact_tokens_for_cspr(Balance::from(1000u128), Balance::zero(), vec![wcspr, token], u64::MAX);
+        });
+    }
+    #[test]
+    fn cspr_slippage_and_deadline() {
+        let user: Address = test_env::get_account(1);
+        let mut factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let wcspr: Address = WrappedCsprDeployer::init().address().to_owned();
+        let mut router: RouterRef = RouterDeployer::init(factory.address().to_owned(), wcspr);
+        let token: Address = deploy_token("TKNA");
+        seed_cspr_pair(&mut factory, &mut router, token, Balance::from(5000u128));
+        Erc20Ref::at(&token).mint(&user, &Balance::from(1000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token).approve(router.address(), &Balance::from(1000u128));
+        // both directions yield 831 out of 1000 in
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            router.with_tokens(Balance::from(1000u128)).swap_exact_cspr_for_tokens(Balance::from(832u128), vec![wcspr, token], u64::MAX);
+        });
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            router.swap_exact_tokens_for_cspr(Balance::from(1000u128), Balance::from(832u128), vec![token, wcspr], u64::MAX);
+        });
+        test_env::advance_block_time_by(100);
+        test_env::assert_exception(Error::Expired, || {
+            router.with_tokens(Balance::from(1000u128)).swap_exact_cspr_for_tokens(Balance::zero(), vec![wcspr, token], 99);
+        });
+        test_env::assert_exception(Error::Expired, || {
+            router.swap_exact_tokens_for_cspr(Balance::from(1000u128), Balance::zero(), vec![token, wcspr], 99);
+        });
+        test_env::assert_exception(Error::Expired, || {
+            router.with_tokens(Balance::from(1000u128)).add_liquidity_cspr(token, Balance::from(1000u128), Balance::zero(), Balance::zero(), 99);
+        });
+    }
+
+    #[test]
+    fn add_liquidity_cspr_refunds_only_the_caller() {
+        let provider: Address = test_env::get_account(2);
+        let user: Address = test_env::get_account(1);
+        let mut factory: AmmFactoryRef = AmmFactoryDeployer::init();
+        let wcspr: Address = WrappedCsprDeployer::init().address().to_owned();
+        let mut router: RouterRef = RouterDeployer::init(factory.address().to_owned(), wcspr);
+        let token: Address = deploy_token("TKNA");
+        let pair: Address = seed_cspr_pair(&mut factory, &mut router, token, Balance::from(5000u128));
+        // tokens sent to the router by mistake stay there
+        Erc20Ref::at(&token).mint(router.address(), &Balance::from(100u128));
+        Erc20Ref::at(&token).mint(&user, &Balance::from(1000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token).approve(router.address(), &Balance::from(1000u128));
+        let before: Balance = test_env::token_balance(user);
+        // the pool takes 1000 of each side at the 1:1 price
+        let shares: Balance = router.with_tokens(Balance::from(2000u128)).add_liquidity_cspr(token, Balance::from(1000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(shares, Balance::from(1000u128));
+        assert_eq!(AmmContractRef::at(&pair).balance_of(&user), shares);
+        assert_eq!(test_env::token_balance(user), before - Balance::from(1000u128));
+        assert_eq!(Erc20Ref::at(&token).balance_of(&user), Balance::zero());
+        assert_eq!(Erc20Ref::at(&token).balance_of(router.address()), Balance::from(100u128));
+        assert_eq!(Erc20Ref::at(&wcspr).balance_of(router.address()), Balance::zero());
+        // the pool keeps no allowance from the router
+        assert_eq!(Erc20Ref::at(&token).allowance(router.address(), &pair), Balance::zero());
+        assert_eq!(Erc20Ref::at(&wcspr).allowance(router.address(), &pair), Balance::zero());
+        assert_eq!(Erc20Ref::at(&token).balance_of(&provider), Balance::zero());
+    }
+}
@@ -0,0 +1,414 @@
+use odra::{Variable, contract_env, execution_error};
+use odra::types::{Address, Balance};
+use odra::types::event::OdraEvent;
+use crate::amm::{Mint, Burn, Swap, MAX_FEE, MINIMUM_LIQUIDITY};
+use crate::erc20::{Erc20, Erc20Ref, Transfer, Approval};
+use crate::math::{_get_d, _get_y, FEE_DENOMINATOR};
+use crate::reentrancy_guard::ReentrancyGuard;
+
+// two token pool on the Curve StableSwap invariant, for assets trading close to a 1:1 peg
+#[odra::module(events = [Mint, Burn, Swap, Transfer, Approval])]
+pub struct StablePool {
+    lq_token: Erc20,
+    token0_address: Variable<Address>,
+    token1_address: Variable<Address>,
+    reserve0: Variable<Balance>,
+    reserve1: Variable<Balance>,
+    amp: Variable<u64>,
+    fee: Variable<u32>,
+    admin: Variable<Address>,
+    guard: ReentrancyGuard
+}
+
+// highest amplification coefficient
+pub const MAX_AMP: u64 = 1_000_000;
+
+execution_error! {
+    pub enum Error {
+        InvalidContribution => 1,
+        InsufficientOutputAmount => 2,
+        Expired => 3,
+        InsufficientLiquidity => 4,
+        InsufficientAmount0 => 5,
+        InsufficientAmount1 => 6,
+        InvalidToken => 7,
+        NotAdmin => 8,
+        FeeTooHigh => 9,
+        InvalidAmplification => 10,
+        InsufficientInitialLiquidity => 11,
+        InsufficientShares => 12,
+        IdenticalAddresses => 13,
+    }
+}
+
+#[odra::module]
+impl StablePool {
+    #[odra(init)]
+    pub fn init(&mut self, token0_address: Address, token1_address: Address, amp: u64, fee: u32, admin: Address) {
+        if token0_address == token1_address {
+            contract_env::revert(Error::IdenticalAddresses)
+        }
+        if amp == 0 || amp > MAX_AMP {
+            contract_env::revert(Error::InvalidAmplification)
+        }
+        if fee > MAX_FEE {
+            contract_env::revert(Error::FeeTooHigh)
+        }
+        // the pool is its own LP token and the only one able to mint or burn it
+        self.lq_token.init_embedded("Stable LP".to_string(), "SLP".to_string(), 18u8);
+        self.token0_address.set(token0_address);
+        self.token1_address.set(token1_address);
+        self.reserve0.set(Balance::zero());
+        self.reserve1.set(Balance::zero());
+        self.amp.set(amp);
+        self.fee.set(fee);
+        self.admin.set(admin);
+    }
+
+    // any ratio can be deposited, the imbalanced part pays half the swap fee
+    pub fn add_liquidity(&mut self, amount0: Balance, amount1: Balance, min_shares: Balance) -> Balance {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        let total_supply: Balance = self.lq_token.total_supply();
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let amp: Balance = Balance::from(self.amp.get().unwrap());
+        // the first contribution has to hold both tokens
+        if total_supply == Balance::zero() && (amount0 == Balance::zero() || amount1 == Balance::zero()) {
+            contract_env::revert(Error::InvalidContribution)
+        }
+        let d0: Balance = _get_d(reserve0, reserve1, amp);
+        let balance0: Balance = reserve0 + amount0;
+        let balance1: Balance = reserve1 + amount1;
+        let d1: Balance = _get_d(balance0, balance1, amp);
+        if d1 <= d0 {
+            contract_env::revert(Error::InvalidContribution)
+        }
+        let shares: Balance = if total_supply == Balance::zero() {
+            // the first MINIMUM_LIQUIDITY shares are locked in the pool forever
+            if d1 <= Balance::from(MINIMUM_LIQUIDITY) {
+                contract_env::revert(Error::InsufficientInitialLiquidity)
+            }
+            self.lq_token.raw_mint(&contract_env::self_address(), &Balance::from(MINIMUM_LIQUIDITY));
+            d1 - Balance::from(MINIMUM_LIQUIDITY)
+        } else {
+            let imbalance_fee: Balance = Balance::from(self.fee.get().unwrap() / 2);
+            let adjusted0: Balance = balance0 - imbalance_fee * abs_diff(d1 * reserve0 / d0, balance0) / Balance::from(FEE_DENOMINATOR);
+            let adjusted1: Balance = balance1 - imbalance_fee * abs_diff(d1 * reserve1 / d0, balance1) / Balance::from(FEE_DENOMINATOR);
+            let d2: Balance = _get_d(adjusted0, adjusted1, amp);
+            total_supply * (d2 - d0) / d0
+        };
+        if shares == Balance::zero() || shares < min_shares {
+            contract_env::revert(Error::InsufficientShares)
+        }
+        Erc20Ref::at(&self.token0_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount0);
+        Erc20Ref::at(&self.token1_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount1);
+        self.lq_token.raw_mint(&caller, &shares);
+        self.update();
+        Mint {
+            sender: caller,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        shares
+    }
+
+    pub fn remove_liquidity(&mut self, shares: Balance, amount0_min: Balance, amount1_min: Balance, deadline: u64) -> (Balance, Balance) {
+        self.guard.lock();
+        if contract_env::get_block_time() > deadline {
+            contract_env::revert(Error::Expired)
+        }
+        let caller: Address = contract_env::caller();
+        let total_supply: Balance = self.lq_token.total_supply();
+        if total_supply == Balance::zero() {
+            contract_env::revert(Error::InsufficientLiquidity)
+        }
+        // withdrawals are proportional and do not move the price
+        let amount0: Balance = shares * self.reserve0.get().unwrap() / total_supply;
+        let amount1: Balance = shares * self.reserve1.get().unwrap() / total_supply;
+        if amount0 < amount0_min {
+            contract_env::revert(Error::InsufficientAmount0)
+        }
+        if amount1 < amount1_min {
+            contract_env::revert(Error::InsufficientAmount1)
+        }
+        self.lq_token.raw_burn(&caller, &shares);
+        Erc20Ref::at(&self.token0_address.get().unwrap()).transfer(&caller, &amount0);
+        Erc20Ref::at(&self.token1_address.get().unwrap()).transfer(&caller, &amount1);
+        self.update();
+        Burn {
+            sender: caller,
+            amount0,
+            amount1,
+            to: caller
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
+    }
+
+    pub fn swap(&mut self, amount: Balance, from_token_address: Address, min_amount_out: Balance) -> Balance {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        let (to_token_address, amount_out) = self.amount_out(amount, from_token_address);
+        if amount_out == Balance::zero() || amount_out < min_amount_out {
+            contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        Erc20Ref::at(&from_token_address).transfer_from(&caller, &contract_env::self_address(), &amount);
+        Erc20Ref::at(&to_token_address).transfer(&caller, &amount_out);
+        self.update();
+        Swap {
+            sender: caller,
+            amount_in: amount,
+            amount_out,
+            token_in: from_token_address,
+            to: caller
+        }
+        .emit();
+        self.guard.unlock();
+        amount_out
+    }
+
+    pub fn get_amount_out(&self, amount_in: Balance, token_in: Address) -> Balance {
+        let (_, amount_out) = self.amount_out(amount_in, token_in);
+        amount_out
+    }
+
+    pub fn set_fee(&mut self, fee: u32) {
+        if contract_env::caller() != self.admin.get().unwrap() {
+            contract_env::revert(Error::NotAdmin)
+        }
+        if fee > MAX_FEE {
+            contract_env::revert(Error::FeeTooHigh)
+        }
+        self.fee.set(fee);
+    }
+
+    pub fn amp(&self) -> u64 {
+        self.amp.get().unwrap()
+    }
+
+    pub fn fee(&self) -> u32 {
+        self.fee.get().unwrap()
+    }
+
+    pub fn admin(&self) -> Address {
+        self.admin.get().unwrap()
+    }
+
+    pub fn reserve0(&self) -> Balance {
+        self.reserve0.get().unwrap()
+    }
+
+    pub fn reserve1(&self) -> Balance {
+        self.reserve1.get().unwrap()
+    }
+
+    pub fn token0(&self) -> Address {
+        self.token0_address.get().unwrap()
+    }
+
+    pub fn token1(&self) -> Address {
+        self.token1_address.get().unwrap()
+    }
+
+    // LP token entry points, minting and burning stay internal to the pool
+    pub fn name(&self) -> String {
+        self.lq_token.name()
+    }
+
+    pub fn symbol(&self) -> String {
+        self.lq_token.symbol()
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.lq_token.decimals()
+    }
+
+    pub fn total_supply(&self) -> Balance {
+        self.lq_token.total_supply()
+    }
+
+    pub fn balance_of(&self, address: &Address) -> Balance {
+        self.lq_token.balance_of(address)
+    }
+
+    pub fn allowance(&self, owner: &Address, spender: &Address) -> Balance {
+        self.lq_token.allowance(owner, spender)
+    }
+
+    pub fn transfer(&mut self, recipient: &Address, amount: &Balance) {
+        self.lq_token.transfer(recipient, amount);
+    }
+
+    pub fn transfer_from(&mut self, owner: &Address, recipient: &Address, amount: &Balance) {
+        self.lq_token.transfer_from(owner, recipient, amount);
+    }
+
+    pub fn approve(&mut self, spender: &Address, amount: &Balance) {
+        self.lq_token.approve(spender, amount);
+    }
+
+    // output token and output amount of a swap from `token_in`, the fee is taken from the output
+    fn amount_out(&self, amount_in: Balance, token_in: Address) -> (Address, Balance) {
+        let token0_address: Address = self.token0_address.get().unwrap();
+        let token1_address: Address = self.token1_address.get().unwrap();
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let (token_out, reserve_in, reserve_out) = if token_in == token0_address {
+            (token1_address, reserve0, reserve1)
+        } else if token_in == token1_address {
+            (token0_address, reserve1, reserve0)
+        } else {
+            contract_env::revert(Error::InvalidToken)
+        };
+        if reserve_in == Balance::zero() || reserve_out == Balance::zero() {
+            contract_env::revert(Error::InsufficientLiquidity)
+        }
+        let amp: Balance = Balance::from(self.amp.get().unwrap());
+        let d: Balance = _get_d(reserve0, reserve1, amp);
+        let y: Balance = _get_y(reserve_in + amount_in, d, amp);
+        // one unit is kept back so rounding in the invariant always favours the pool
+        if y + Balance::from(1) >= reserve_out {
+            return (token_out, Balance::zero());
+        }
+        let dy: Balance = reserve_out - y - Balance::from(1);
+        let fee: Balance = dy * Balance::from(self.fee.get().unwrap()) / Balance::from(FEE_DENOMINATOR);
+        (token_out, dy - fee)
+    }
+
+    // sets the reserves to the current token balances of the pool
+    fn update(&mut self) {
+        self.reserve0.set(Erc20Ref::at(&self.token0_address.get().unwrap()).balance_of(&contract_env::self_address()));
+        self.reserve1.set(Erc20Ref::at(&self.token1_address.get().unwrap()).balance_of(&contract_env::self_address()));
+    }
+}
+
+fn abs_diff(x: Balance, y: Balance) -> Balance {
+    if x > y {
+        x - y
+    } else {
+        y - x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use odra::test_env;
+    use odra::types::{Address, Balance, OdraError, VmError};
+    use crate::amm::MINIMUM_LIQUIDITY;
+    use crate::erc20::{Erc20Deployer, Erc20Ref};
+    use super::{Error, StablePoolDeployer, StablePoolRef};
+
+    fn deploy_token(symbol: &str) -> Address {
+        Erc20Deployer::init(symbol.to_string(), symbol.to_string(), 18u8, &Balance::from(0u128)).address().to_owned()
+    }
+
+    // pool with amplification 100, a 0.04% fee and `amount` of each token deposited by account 1
+    fn setup(amount: Balance) -> (Address, StablePoolRef) {
+        let user: Address = test_env::get_account(1);
+        let token0_address: Address = deploy_token("USDA");
+        let token1_address: Address = deploy_token("USDB");
+        let mut pool: StablePoolRef = StablePoolDeployer::init(token0_address, token1_address, 100, 4, test_env::get_account(0));
+        Erc20Ref::at(&token0_address).mint(&user, &amount);
+        Erc20Ref::at(&token1_address).mint(&user, &amount);
+        test_env::set_caller(user);
+        Erc20Ref::at(&token0_address).approve(pool.address(), &amount);
+        Erc20Ref::at(&token1_address).approve(pool.address(), &amount);
+        pool.add_liquidity(amount, amount, Balance::zero());
+        test_env::set_caller(test_env::get_account(0));
+        (user, pool)
+    }
+
+    #[test]
+    fn add_liquidity() {
+        let (user, pool) = setup(Balance::from(1_000_000u128));
+        // D of a balanced pool is the sum of the balances
+        assert_eq!(pool.total_supply(), Balance::from(2_000_000u128));
+        assert_eq!(pool.balance_of(&user), Balance::from(2_000_000u128 - MINIMUM_LIQUIDITY));
+        assert_eq!(pool.reserve0(), Balance::from(1_000_000u128));
+        assert_eq!(pool.reserve1(), Balance::from(1_000_000u128));
+        // shares are only minted by the pool itself
+        test_env::assert_exception(OdraError::VmError(VmError::NoSuchMethod("mint".to_string())), || {
+            Erc20Ref::at(pool.address()).mint(&user, &Balance::from(1_000u128));
+        });
+    }
+
+    #[test]
+    fn imbalanced_add_liquidity() {
+        let (user, mut pool) = setup(Balance::from(1_000_000u128));
+        Erc20Ref::at(&pool.token0()).mint(&user, &Balance::from(20_000u128));
+        Erc20Ref::at(&pool.token1()).mint(&user, &Balance::from(10_000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&pool.token0()).approve(pool.address(), &Balance::from(20_000u128));
+        Erc20Ref::at(&pool.token1()).approve(pool.address(), &Balance::from(10_000u128));
+        let balanced: Balance = pool.add_liquidity(Balance::from(10_000u128), Balance::from(10_000u128), Balance::zero());
+        assert_eq!(balanced, Balance::from(20_000u128));
+        // a one sided deposit pays the imbalance fee
+        let one_sided: Balance = pool.add_liquidity(Balance::from(10_000u128), Balance::zero(), Balance::zero());
+        assert!(one_sided < Balance::from(10_000u128));
+        assert!(one_sided > Balance::from(9_900u128));
+    }
+
+    #[test]
+    fn swap() {
+        let (user, mut pool) = setup(Balance::from(1_000_000u128));
+        let token0_address: Address = pool.token0();
+        let token1_address: Address = pool.token1();
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(100_000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token0_address).approve(pool.address(), &Balance::from(100_000u128));
+        let quoted: Balance = pool.get_amount_out(Balance::from(100_000u128), token0_address);
+        let amount_out: Balance = pool.swap(Balance::from(100_000u128), token0_address, Balance::zero());
+        assert_eq!(amount_out, quoted);
+        // a constant product pool would pay out 90909 before fees
+        assert_eq!(amount_out, Balance::from(99_861u128));
+        assert_eq!(Erc20Ref::at(&token1_address).balance_of(&user), amount_out);
+        assert_eq!(pool.reserve0(), Balance::from(1_100_000u128));
+        assert_eq!(pool.reserve1(), Balance::from(1_000_000u128) - amount_out);
+    }
+
+    #[test]
+    fn swap_errors() {
+        let (user, mut pool) = setup(Balance::from(1_000_000u128));
+        let token0_address: Address = pool.token0();
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(1_000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token0_address).approve(pool.address(), &Balance::from(1_000u128));
+        test_env::assert_exception(Error::InvalidToken, || {
+            pool.swap(Balance::from(1_000u128), user, Balance::zero());
+        });
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            pool.swap(Balance::from(1_000u128), token0_address, Balance::from(1_000u128));
+        });
+    }
+
+    #[test]
+    fn remove_liquidity() {
+        let (user, mut pool) = setup(Balance::from(1_000_000u128));
+        test_env::set_caller(user);
+        let amounts: (Balance, Balance) = pool.remove_liquidity(Balance::from(1_000_000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(amounts, (Balance::from(500_000u128), Balance::from(500_000u128)));
+        assert_eq!(pool.balance_of(&user), Balance::from(1_000_000u128 - MINIMUM_LIQUIDITY));
+        test_env::assert_exception(Error::InsufficientAmount0, || {
+            pool.remove_liquidity(Balance::from(1_000u128), Balance::from(501u128), Balance::zero(), u64::MAX);
+        });
+    }
+
+    #[test]
+    fn init_errors() {
+        let token0: Address = deploy_token("USDA");
+        let token1: Address = deploy_token("USDB");
+        test_env::assert_exception(Error::IdenticalAddresses, || {
+            StablePoolDeployer::init(token0, token0, 100, 4, test_env::get_account(0));
+        });
+        test_env::assert_exception(Error::InvalidAmplification, || {
+            StablePoolDeployer::init(token0, token1, 0, 4, test_env::get_account(0));
+        });
+        test_env::assert_exception(Error::FeeTooHigh, || {
+            StablePoolDeployer::init(token0, token1, 100, 1001, test_env::get_account(0));
+        });
+    }
+}
@@ -0,0 +1,470 @@
+use odra::{Variable, Mapping, contract_env, execution_error, Event};
+use odra::types::{Address, Balance};
+use odra::types::event::OdraEvent;
+use crate::amm::{MAX_FEE, MINIMUM_LIQUIDITY};
+use crate::erc20::Erc20Ref;
+use crate::factory::{sort_tokens, DEFAULT_FEE};
+use crate::math::{_sqrt, _min, _quote, _get_amount_out};
+use crate::reentrancy_guard::ReentrancyGuard;
+
+// keeps the tokens, reserves, fees and LP shares of every pool in a single contract,
+// pools are addressed by their id instead of a contract address
+#[odra::module(events = [PoolCreated, Mint, Burn, Swap])]
+pub struct AmmVault {
+    admin: Variable<Address>,
+    pool_count: Variable<u32>,
+    pool_ids: Mapping<Address, Mapping<Address, u32>>,
+    token0: Mapping<u32, Address>,
+    token1: Mapping<u32, Address>,
+    reserve0: Mapping<u32, Balance>,
+    reserve1: Mapping<u32, Balance>,
+    fee: Mapping<u32, u32>,
+    total_supply: Mapping<u32, Balance>,
+    shares: Mapping<u32, Mapping<Address, Balance>>,
+    guard: ReentrancyGuard
+}
+
+execution_error! {
+    pub enum Error {
+        IdenticalAddresses => 1,
+        PoolExists => 2,
+        PoolNotFound => 3,
+        InvalidPath => 4,
+        NotAdmin => 5,
+        FeeTooHigh => 6,
+        InsufficientInitialLiquidity => 7,
+        InvalidContribution => 8,
+        InsufficientAmount0 => 9,
+        InsufficientAmount1 => 10,
+        InsufficientShares => 11,
+        InsufficientOutputAmount => 12,
+        InsufficientLiquidity => 13,
+        Expired => 14,
+    }
+}
+
+#[odra::module]
+impl AmmVault {
+    #[odra(init)]
+    pub fn init(&mut self) {
+        self.admin.set(contract_env::caller());
+    }
+
+    pub fn create_pool(&mut self, token_a: Address, token_b: Address) -> u32 {
+        if token_a == token_b {
+            contract_env::revert(Error::IdenticalAddresses)
+        }
+        let (token0, token1) = sort_tokens(token_a, token_b);
+        if self.pool_id(token0, token1).is_some() {
+            contract_env::revert(Error::PoolExists)
+        }
+        let pool_id: u32 = self.pool_count.get_or_default();
+        self.pool_ids.get_instance(&token0).set(&token1, pool_id);
+        self.pool_ids.get_instance(&token1).set(&token0, pool_id);
+        self.token0.set(&pool_id, token0);
+        self.token1.set(&pool_id, token1);
+        self.reserve0.set(&pool_id, Balance::zero());
+        self.reserve1.set(&pool_id, Balance::zero());
+        self.fee.set(&pool_id, DEFAULT_FEE);
+        self.total_supply.set(&pool_id, Balance::zero());
+        self.pool_count.set(pool_id + 1);
+        PoolCreated {
+            pool_id,
+            token0,
+            token1
+        }
+        .emit();
+        pool_id
+    }
+
+    pub fn add_liquidity(&mut self, pool_id: u32, amount0_desired: Balance, amount1_desired: Balance, amount0_min: Balance, amount1_min: Balance) -> Balance {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        let (token0, token1) = self.tokens(pool_id);
+        let (reserve0, reserve1) = self.reserves(pool_id);
+        let total_supply: Balance = self.total_supply.get_or_default(&pool_id);
+        // calculate the optimal contribution for the current reserves
+        let (amount0, amount1) = optimal_amounts(reserve0, reserve1, amount0_desired, amount1_desired, amount0_min, amount1_min);
+        let shares: Balance = if total_supply == Balance::zero() {
+            // the first MINIMUM_LIQUIDITY shares are locked in the vault forever
+            let root_k: Balance = _sqrt(amount0 * amount1);
+            if root_k <= Balance::from(MINIMUM_LIQUIDITY) {
+                contract_env::revert(Error::InsufficientInitialLiquidity)
+            }
+            self.mint_shares(pool_id, contract_env::self_address(), Balance::from(MINIMUM_LIQUIDITY));
+            root_k - Balance::from(MINIMUM_LIQUIDITY)
+        } else {
+            _min(amount0 * total_supply / reserve0, amount1 * total_supply / reserve1)
+        };
+        if shares == Balance::zero() {
+            contract_env::revert(Error::InvalidContribution)
+        }
+        Erc20Ref::at(&token0).transfer_from(&caller, &contract_env::self_address(), &amount0);
+        Erc20Ref::at(&token1).transfer_from(&caller, &contract_env::self_address(), &amount1);
+        self.mint_shares(pool_id, caller, shares);
+        self.reserve0.set(&pool_id, reserve0 + amount0);
+        self.reserve1.set(&pool_id, reserve1 + amount1);
+        Mint {
+            pool_id,
+            sender: caller,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        shares
+    }
+
+    pub fn remove_liquidity(&mut self, pool_id: u32, shares: Balance, amount0_min: Balance, amount1_min: Balance, deadline: u64) -> (Balance, Balance) {
+        self.guard.lock();
+        ensure(deadline);
+        let caller: Address = contract_env::caller();
+        let (token0, token1) = self.tokens(pool_id);
+        let (reserve0, reserve1) = self.reserves(pool_id);
+        let total_supply: Balance = self.total_supply.get_or_default(&pool_id);
+        let balance: Balance = self.balance_of(pool_id, caller);
+        if shares == Balance::zero() || shares > balance {
+            contract_env::revert(Error::InsufficientShares)
+        }
+        // the vault holds the tokens of all pools, so the pool reserves are the source of truth
+        let amount0: Balance = shares * reserve0 / total_supply;
+        let amount1: Balance = shares * reserve1 / total_supply;
+        if amount0 < amount0_min {
+            contract_env::revert(Error::InsufficientAmount0)
+        }
+        if amount1 < amount1_min {
+            contract_env::revert(Error::InsufficientAmount1)
+        }
+        self.shares.get_instance(&pool_id).set(&caller, balance - shares);
+        self.total_supply.set(&pool_id, total_supply - shares);
+        self.reserve0.set(&pool_id, reserve0 - amount0);
+        self.reserve1.set(&pool_id, reserve1 - amount1);
+        Erc20Ref::at(&token0).transfer(&caller, &amount0);
+        Erc20Ref::at(&token1).transfer(&caller, &amount1);
+        Burn {
+            pool_id,
+            sender: caller,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
+    }
+
+    // swaps along `path` by updating the reserves of each pool, tokens only move once per token at the end
+    pub fn swap_exact_tokens_for_tokens(&mut self, amount_in: Balance, min_amount_out: Balance, path: Vec<Address>, deadline: u64) -> Balance {
+        self.guard.lock();
+        ensure(deadline);
+        if path.len() < 2 {
+            contract_env::revert(Error::InvalidPath)
+        }
+        let caller: Address = contract_env::caller();
+        let mut deltas: Vec<Delta> = Vec::new();
+        let mut amount: Balance = amount_in;
+        for hop in path.windows(2) {
+            let pool_id: u32 = match self.pool_id(hop[0], hop[1]) {
+                Some(pool_id) => pool_id,
+                None => contract_env::revert(Error::PoolNotFound)
+            };
+            let amount_out: Balance = self.swap_in_pool(pool_id, amount, hop[0]);
+            Swap {
+                pool_id,
+                sender: caller,
+                amount_in: amount,
+                amount_out,
+                token_in: hop[0]
+            }
+            .emit();
+            add_delta(&mut deltas, hop[0], amount, Balance::zero());
+            add_delta(&mut deltas, hop[1], Balance::zero(), amount_out);
+            amount = amount_out;
+        }
+        if amount < min_amount_out {
+            contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        // settle the net movement of every token touched by the path
+        for delta in deltas.iter() {
+            if delta.owed_in > delta.owed_out {
+                Erc20Ref::at(&delta.token).transfer_from(&caller, &contract_env::self_address(), &(delta.owed_in - delta.owed_out));
+            } else if delta.owed_out > delta.owed_in {
+                Erc20Ref::at(&delta.token).transfer(&caller, &(delta.owed_out - delta.owed_in));
+            }
+        }
+        self.guard.unlock();
+        amount
+    }
+
+    pub fn set_fee(&mut self, pool_id: u32, fee: u32) {
+        if contract_env::caller() != self.admin.get().unwrap() {
+            contract_env::revert(Error::NotAdmin)
+        }
+        if fee > MAX_FEE {
+            contract_env::revert(Error::FeeTooHigh)
+        }
+        // reverts for unknown pools
+        self.tokens(pool_id);
+        self.fee.set(&pool_id, fee);
+    }
+
+    pub fn admin(&self) -> Address {
+        self.admin.get().unwrap()
+    }
+
+    pub fn pool_id(&self, token_a: Address, token_b: Address) -> Option<u32> {
+        self.pool_ids.get_instance(&token_a).get(&token_b)
+    }
+
+    pub fn pool_count(&self) -> u32 {
+        self.pool_count.get_or_default()
+    }
+
+    pub fn tokens(&self, pool_id: u32) -> (Address, Address) {
+        match (self.token0.get(&pool_id), self.token1.get(&pool_id)) {
+            (Some(token0), Some(token1)) => (token0, token1),
+            _ => contract_env::revert(Error::PoolNotFound)
+        }
+    }
+
+    pub fn reserves(&self, pool_id: u32) -> (Balance, Balance) {
+        (self.reserve0.get_or_default(&pool_id), self.reserve1.get_or_default(&pool_id))
+    }
+
+    pub fn fee(&self, pool_id: u32) -> u32 {
+        self.fee.get_or_default(&pool_id)
+    }
+
+    pub fn total_supply(&self, pool_id: u32) -> Balance {
+        self.total_supply.get_or_default(&pool_id)
+    }
+
+    pub fn balance_of(&self, pool_id: u32, owner: Address) -> Balance {
+        self.shares.get_instance(&pool_id).get_or_default(&owner)
+    }
+
+    fn mint_shares(&mut self, pool_id: u32, owner: Address, shares: Balance) {
+        let balance: Balance = self.balance_of(pool_id, owner);
+        self.shares.get_instance(&pool_id).set(&owner, balance + shares);
+        self.total_supply.set(&pool_id, self.total_supply(pool_id) + shares);
+    }
+
+    // moves `amount_in` of `token_in` into the reserves of the pool and returns the output amount
+    fn swap_in_pool(&mut self, pool_id: u32, amount_in: Balance, token_in: Address) -> Balance {
+        let (token0, _) = self.tokens(pool_id);
+        let (reserve0, reserve1) = self.reserves(pool_id);
+        if reserve0 == Balance::zero() || reserve1 == Balance::zero() {
+            contract_env::revert(Error::InsufficientLiquidity)
+        }
+        let fee: u32 = self.fee(pool_id);
+        if token_in == token0 {
+            let amount_out: Balance = _get_amount_out(amount_in, reserve0, reserve1, fee);
+            self.reserve0.set(&pool_id, reserve0 + amount_in);
+            self.reserve1.set(&pool_id, reserve1 - amount_out);
+            amount_out
+        } else {
+            let amount_out: Balance = _get_amount_out(amount_in, reserve1, reserve0, fee);
+            self.reserve1.set(&pool_id, reserve1 + amount_in);
+            self.reserve0.set(&pool_id, reserve0 - amount_out);
+            amount_out
+        }
+    }
+}
+
+// amounts of a token owed to the vault and to the trader over a multi-hop swap
+struct Delta {
+    token: Address,
+    owed_in: Balance,
+    owed_out: Balance
+}
+
+fn add_delta(deltas: &mut Vec<Delta>, token: Address, owed_in: Balance, owed_out: Balance) {
+    match deltas.iter_mut().find(|delta| delta.token == token) {
+        Some(delta) => {
+            delta.owed_in = delta.owed_in + owed_in;
+            delta.owed_out = delta.owed_out + owed_out;
+        }
+        None => deltas.push(Delta { token, owed_in, owed_out })
+    }
+}
+
+fn optimal_amounts(reserve0: Balance, reserve1: Balance, amount0_desired: Balance, amount1_desired: Balance, amount0_min: Balance, amount1_min: Balance) -> (Balance, Balance) {
+    // the first contribution sets the price
+    if reserve0 == Balance::zero() && reserve1 == Balance::zero() {
+        return (amount0_desired, amount1_desired);
+    }
+    let amount1_optimal: Balance = _quote(amount0_desired, reserve0, reserve1);
+    if amount1_optimal <= amount1_desired {
+        if amount1_optimal < amount1_min {
+            contract_env::revert(Error::InsufficientAmount1)
+        }
+        return (amount0_desired, amount1_optimal);
+    }
+    let amount0_optimal: Balance = _quote(amount1_desired, reserve1, reserve0);
+    if amount0_optimal < amount0_min {
+        contract_env::revert(Error::InsufficientAmount0)
+    }
+    (amount0_optimal, amount1_desired)
+}
+
+fn ensure(deadline: u64) {
+    if contract_env::get_block_time() > deadline {
+        contract_env::revert(Error::Expired)
+    }
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct PoolCreated {
+    pub pool_id: u32,
+    pub token0: Address,
+    pub token1: Address
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Mint {
+    pub pool_id: u32,
+    pub sender: Address,
+    pub amount0: Balance,
+    pub amount1: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Burn {
+    pub pool_id: u32,
+    pub sender: Address,
+    pub amount0: Balance,
+    pub amount1: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Swap {
+    pub pool_id: u32,
+    pub sender: Address,
+    pub amount_in: Balance,
+    pub amount_out: Balance,
+    pub token_in: Address
+}
+
+#[cfg(test)]
+mod tests {
+    use odra::{assert_events, test_env};
+    use odra::types::{Address, Balance};
+    use crate::erc20::{Erc20Deployer, Erc20Ref};
+    use super::{AmmVaultDeployer, AmmVaultRef, Error, PoolCreated, Swap};
+
+    fn deploy_token(symbol: &str) -> Address {
+        Erc20Deployer::init(symbol.to_string(), symbol.to_string(), 18u8, &Balance::from(0u128)).address().to_owned()
+    }
+
+    fn seed_pool(vault: &mut AmmVaultRef, token_a: Address, token_b: Address, amount: Balance) -> u32 {
+        let provider: Address = test_env::get_account(2);
+        let pool_id: u32 = vault.create_pool(token_a, token_b);
+        Erc20Ref::at(&token_a).mint(&provider, &amount);
+        Erc20Ref::at(&token_b).mint(&provider, &amount);
+        test_env::set_caller(provider);
+        Erc20Ref::at(&token_a).approve(vault.address(), &amount);
+        Erc20Ref::at(&token_b).approve(vault.address(), &amount);
+        vault.add_liquidity(pool_id, amount, amount, Balance::zero(), Balance::zero());
+        test_env::set_caller(test_env::get_account(0));
+        pool_id
+    }
+
+    #[test]
+    fn create_pool() {
+        let mut vault: AmmVaultRef = AmmVaultDeployer::init();
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let token_c: Address = deploy_token("TKNC");
+        assert_eq!(vault.create_pool(token_a, token_b), 0);
+        assert_eq!(vault.create_pool(token_c, token_b), 1);
+        assert_eq!(vault.pool_count(), 2);
+        assert_eq!(vault.pool_id(token_b, token_a), Some(0));
+        assert_eq!(vault.pool_id(token_a, token_c), None);
+        let (token0, token1) = vault.tokens(1);
+        assert!(token0 < token1);
+        assert_events!(vault, PoolCreated { pool_id: 1, token0, token1 });
+        test_env::assert_exception(Error::PoolExists, || {
+            vault.create_pool(token_b, token_a);
+        });
+        test_env::assert_exception(Error::IdenticalAddresses, || {
+            vault.create_pool(token_a, token_a);
+        });
+        test_env::assert_exception(Error::PoolNotFound, || {
+            vault.tokens(2);
+        });
+    }
+
+    #[test]
+    fn liquidity() {
+        let provider: Address = test_env::get_account(2);
+        let mut vault: AmmVaultRef = AmmVaultDeployer::init();
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let pool_id: u32 = seed_pool(&mut vault, token_a, token_b, Balance::from(5000u128));
+        // the first MINIMUM_LIQUIDITY shares belong to the vault
+        assert_eq!(vault.balance_of(pool_id, provider), Balance::from(4000u128));
+        assert_eq!(vault.balance_of(pool_id, *vault.address()), Balance::from(1000u128));
+        assert_eq!(vault.total_supply(pool_id), Balance::from(5000u128));
+        test_env::set_caller(provider);
+        test_env::assert_exception(Error::InsufficientShares, || {
+            vault.remove_liquidity(pool_id, Balance::from(4001u128), Balance::zero(), Balance::zero(), u64::MAX);
+        });
+        let amounts: (Balance, Balance) = vault.remove_liquidity(pool_id, Balance::from(2000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(amounts, (Balance::from(2000u128), Balance::from(2000u128)));
+        assert_eq!(vault.reserves(pool_id), (Balance::from(3000u128), Balance::from(3000u128)));
+        assert_eq!(Erc20Ref::at(&token_a).balance_of(&provider), Balance::from(2000u128));
+        assert_eq!(Erc20Ref::at(&token_a).balance_of(vault.address()), Balance::from(3000u128));
+    }
+
+    #[test]
+    fn multi_hop_swap() {
+        let user: Address = test_env::get_account(1);
+        let mut vault: AmmVaultRef = AmmVaultDeployer::init();
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let token_c: Address = deploy_token("TKNC");
+        let pool_ab: u32 = seed_pool(&mut vault, token_a, token_b, Balance::from(5000u128));
+        let pool_bc: u32 = seed_pool(&mut vault, token_b, token_c, Balance::from(5000u128));
+        Erc20Ref::at(&token_a).mint(&user, &Balance::from(1000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token_a).approve(vault.address(), &Balance::from(1000u128));
+        // 1000 A -> 831 B -> 710 C
+        let amount_out: Balance = vault.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::from(710u128), vec![token_a, token_b, token_c], u64::MAX);
+        assert_eq!(amount_out, Balance::from(710u128));
+        assert_eq!(Erc20Ref::at(&token_c).balance_of(&user), Balance::from(710u128));
+        assert_eq!(Erc20Ref::at(&token_a).balance_of(&user), Balance::zero());
+        // the intermediate token never left the vault, only the reserves moved
+        assert_eq!(Erc20Ref::at(&token_b).balance_of(vault.address()), Balance::from(10000u128));
+        assert_eq!(Erc20Ref::at(&token_b).balance_of(&user), Balance::zero());
+        let (reserve_ab0, reserve_ab1) = vault.reserves(pool_ab);
+        let (reserve_bc0, reserve_bc1) = vault.reserves(pool_bc);
+        assert_eq!(reserve_ab0 + reserve_ab1, Balance::from(10169u128));
+        assert_eq!(reserve_bc0 + reserve_bc1, Balance::from(10121u128));
+        assert_events!(vault, Swap { pool_id: pool_bc, sender: user, amount_in: Balance::from(831u128), amount_out: Balance::from(710u128), token_in: token_b });
+    }
+
+    #[test]
+    fn swap_errors() {
+        let user: Address = test_env::get_account(1);
+        let mut vault: AmmVaultRef = AmmVaultDeployer::init();
+        let token_a: Address = deploy_token("TKNA");
+        let token_b: Address = deploy_token("TKNB");
+        let token_c: Address = deploy_token("TKNC");
+        seed_pool(&mut vault, token_a, token_b, Balance::from(5000u128));
+        Erc20Ref::at(&token_a).mint(&user, &Balance::from(1000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token_a).approve(vault.address(), &Balance::from(1000u128));
+        test_env::assert_exception(Error::InvalidPath, || {
+            vault.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::zero(), vec![token_a], u64::MAX);
+        });
+        test_env::assert_exception(Error::PoolNotFound, || {
+            vault.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::zero(), vec![token_a, token_c], u64::MAX);
+        });
+        test_env::assert_exception(Error::InsufficientOutputAmount, || {
+            vault.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::from(832u128), vec![token_a, token_b], u64::MAX);
+        });
+        test_env::advance_block_time_by(100);
+        test_env::assert_exception(Error::Expired, || {
+            vault.swap_exact_tokens_for_tokens(Balance::from(1000u128), Balance::zero(), vec![token_a, token_b], 99);
+        });
+    }
+}
@@ -0,0 +1,464 @@
+use odra::{Variable, contract_env, execution_error};
+use odra::types::{Address, Balance};
+use odra::types::event::OdraEvent;
+use crate::amm::{Mint, Burn, Swap, MAX_FEE, MINIMUM_LIQUIDITY};
+use crate::erc20::{Erc20, Erc20Ref, Transfer, Approval};
+use crate::math::{_sqrt, _weighted_amount_out, _weighted_amount_in, FEE_DENOMINATOR};
+use crate::reentrancy_guard::ReentrancyGuard;
+
+// two token pool on the Balancer weighted product invariant, weights are fixed at init
+#[odra::module(events = [Mint, Burn, Swap, Transfer, Approval])]
+pub struct WeightedPool {
+    lq_token: Erc20,
+    token0_address: Variable<Address>,
+    token1_address: Variable<Address>,
+    weight0: Variable<u32>,
+    weight1: Variable<u32>,
+    reserve0: Variable<Balance>,
+    reserve1: Variable<Balance>,
+    fee: Variable<u32>,
+    admin: Variable<Address>,
+    guard: ReentrancyGuard
+}
+
+// weights are expressed in basis points and sum up to FEE_DENOMINATOR
+pub const MIN_WEIGHT: u32 = 100;
+// largest share of the input reserve a swap may add, in basis points
+pub const MAX_IN_RATIO: u32 = 3000;
+// largest share of the output reserve a swap may take, in basis points
+pub const MAX_OUT_RATIO: u32 = 3000;
+
+execution_error! {
+    pub enum Error {
+        InvalidWeights => 1,
+        FeeTooHigh => 2,
+        NotAdmin => 3,
+        AlreadyInitialized => 4,
+        NotInitialized => 5,
+        InsufficientInitialLiquidity => 6,
+        ExcessiveInputAmount => 7,
+        InsufficientOutputAmount => 8,
+        InsufficientAmount0 => 9,
+        InsufficientAmount1 => 10,
+        InvalidToken => 11,
+        MaxInRatio => 12,
+        MaxOutRatio => 13,
+        Expired => 14,
+        IdenticalAddresses => 15,
+    }
+}
+
+#[odra::module]
+impl WeightedPool {
+    #[odra(init)]
+    pub fn init(&mut self, token0_address: Address, token1_address: Address, weight0: u32, weight1: u32, fee: u32, admin: Address) {
+        if token0_address == token1_address {
+            contract_env::revert(Error::IdenticalAddresses)
+        }
+        if weight0 < MIN_WEIGHT || weight1 < MIN_WEIGHT || weight0.checked_add(weight1) != Some(FEE_DENOMINATOR) {
+            contract_env::revert(Error::InvalidWeights)
+        }
+        if fee > MAX_FEE {
+            contract_env::revert(Error::FeeTooHigh)
+        }
+        // the pool is its own LP token and the only one able to mint or burn it
+        self.lq_token.init_embedded("Weighted LP".to_string(), "WLP".to_string(), 18u8);
+        self.token0_address.set(token0_address);
+        self.token1_address.set(token1_address);
+        self.weight0.set(weight0);
+        self.weight1.set(weight1);
+        self.reserve0.set(Balance::zero());
+        self.reserve1.set(Balance::zero());
+        self.fee.set(fee);
+        self.admin.set(admin);
+    }
+
+    // the first deposit sets the price, later deposits go through `join_pool`
+    pub fn initialize(&mut self, amount0: Balance, amount1: Balance) -> Balance {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        if self.lq_token.total_supply() != Balance::zero() {
+            contract_env::revert(Error::AlreadyInitialized)
+        }
+        // the first MINIMUM_LIQUIDITY shares are locked in the pool forever
+        let root_k: Balance = _sqrt(amount0 * amount1);
+        if root_k <= Balance::from(MINIMUM_LIQUIDITY) {
+            contract_env::revert(Error::InsufficientInitialLiquidity)
+        }
+        let shares: Balance = root_k - Balance::from(MINIMUM_LIQUIDITY);
+        Erc20Ref::at(&self.token0_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount0);
+        Erc20Ref::at(&self.token1_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount1);
+        self.lq_token.raw_mint(&contract_env::self_address(), &Balance::from(MINIMUM_LIQUIDITY));
+        self.lq_token.raw_mint(&caller, &shares);
+        self.update();
+        Mint {
+            sender: caller,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        shares
+    }
+
+    // mints exactly `shares` for a proportional deposit, rounded up in favour of the pool
+    pub fn join_pool(&mut self, shares: Balance, max_amount0: Balance, max_amount1: Balance) -> (Balance, Balance) {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        let total_supply: Balance = self.lq_token.total_supply();
+        if total_supply == Balance::zero() {
+            contract_env::revert(Error::NotInitialized)
+        }
+        let amount0: Balance = (shares * self.reserve0.get().unwrap() + total_supply - Balance::from(1)) / total_supply;
+        let amount1: Balance = (shares * self.reserve1.get().unwrap() + total_supply - Balance::from(1)) / total_supply;
+        if amount0 > max_amount0 || amount1 > max_amount1 {
+            contract_env::revert(Error::ExcessiveInputAmount)
+        }
+        Erc20Ref::at(&self.token0_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount0);
+        Erc20Ref::at(&self.token1_address.get().unwrap()).transfer_from(&caller, &contract_env::self_address(), &amount1);
+        self.lq_token.raw_mint(&caller, &shares);
+        self.update();
+        Mint {
+            sender: caller,
+            amount0,
+            amount1
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
+    }
+
+    // burns `shares` for a proportional withdrawal, rounded down in favour of the pool
+    pub fn exit_pool(&mut self, shares: Balance, min_amount0: Balance, min_amount1: Balance, deadline: u64) -> (Balance, Balance) {
+        self.guard.lock();
+        if contract_env::get_block_time() > deadline {
+            contract_env::revert(Error::Expired)
+        }
+        let caller: Address = contract_env::caller();
+        let total_supply: Balance = self.lq_token.total_supply();
+        if total_supply == Balance::zero() {
+            contract_env::revert(Error::NotInitialized)
+        }
+        let amount0: Balance = shares * self.reserve0.get().unwrap() / total_supply;
+        let amount1: Balance = shares * self.reserve1.get().unwrap() / total_supply;
+        if amount0 < min_amount0 {
+            contract_env::revert(Error::InsufficientAmount0)
+        }
+        if amount1 < min_amount1 {
+            contract_env::revert(Error::InsufficientAmount1)
+        }
+        self.lq_token.raw_burn(&caller, &shares);
+        Erc20Ref::at(&self.token0_address.get().unwrap()).transfer(&caller, &amount0);
+        Erc20Ref::at(&self.token1_address.get().unwrap()).transfer(&caller, &amount1);
+        self.update();
+        Burn {
+            sender: caller,
+            amount0,
+            amount1,
+            to: caller
+        }
+        .emit();
+        self.guard.unlock();
+        (amount0, amount1)
+    }
+
+    pub fn swap_exact_in(&mut self, amount_in: Balance, token_in: Address, min_amount_out: Balance) -> Balance {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        let (token_out, amount_out) = self.amount_out(amount_in, token_in);
+        if amount_out == Balance::zero() || amount_out < min_amount_out {
+            contract_env::revert(Error::InsufficientOutputAmount)
+        }
+        self.settle(caller, token_in, amount_in, token_out, amount_out);
+        self.guard.unlock();
+        amount_out
+    }
+
+    pub fn swap_exact_out(&mut self, amount_out: Balance, token_out: Address, max_amount_in: Balance) -> Balance {
+        self.guard.lock();
+        let caller: Address = contract_env::caller();
+        let (token_in, amount_in) = self.amount_in(amount_out, token_out);
+        if amount_in > max_amount_in {
+            contract_env::revert(Error::ExcessiveInputAmount)
+        }
+        self.settle(caller, token_in, amount_in, token_out, amount_out);
+        self.guard.unlock();
+        amount_in
+    }
+
+    pub fn get_amount_out(&self, amount_in: Balance, token_in: Address) -> Balance {
+        let (_, amount_out) = self.amount_out(amount_in, token_in);
+        amount_out
+    }
+
+    pub fn get_amount_in(&self, amount_out: Balance, token_out: Address) -> Balance {
+        let (_, amount_in) = self.amount_in(amount_out, token_out);
+        amount_in
+    }
+
+    pub fn set_fee(&mut self, fee: u32) {
+        if contract_env::caller() != self.admin.get().unwrap() {
+            contract_env::revert(Error::NotAdmin)
+        }
+        if fee > MAX_FEE {
+            contract_env::revert(Error::FeeTooHigh)
+        }
+        self.fee.set(fee);
+    }
+
+    pub fn weights(&self) -> (u32, u32) {
+        (self.weight0.get().unwrap(), self.weight1.get().unwrap())
+    }
+
+    pub fn fee(&self) -> u32 {
+        self.fee.get().unwrap()
+    }
+
+    pub fn admin(&self) -> Address {
+        self.admin.get().unwrap()
+    }
+
+    pub fn reserve0(&self) -> Balance {
+        self.reserve0.get().unwrap()
+    }
+
+    pub fn reserve1(&self) -> Balance {
+        self.reserve1.get().unwrap()
+    }
+
+    pub fn token0(&self) -> Address {
+        self.token0_address.get().unwrap()
+    }
+
+    pub fn token1(&self) -> Address {
+        self.token1_address.get().unwrap()
+    }
+
+    // LP token entry points, minting and burning stay internal to the pool
+    pub fn name(&self) -> String {
+        self.lq_token.name()
+    }
+
+    pub fn symbol(&self) -> String {
+        self.lq_token.symbol()
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.lq_token.decimals()
+    }
+
+    pub fn total_supply(&self) -> Balance {
+        self.lq_token.total_supply()
+    }
+
+    pub fn balance_of(&self, address: &Address) -> Balance {
+        self.lq_token.balance_of(address)
+    }
+
+    pub fn allowance(&self, owner: &Address, spender: &Address) -> Balance {
+        self.lq_token.allowance(owner, spender)
+    }
+
+    pub fn transfer(&mut self, recipient: &Address, amount: &Balance) {
+        self.lq_token.transfer(recipient, amount);
+    }
+
+    pub fn transfer_from(&mut self, owner: &Address, recipient: &Address, amount: &Balance) {
+        self.lq_token.transfer_from(owner, recipient, amount);
+    }
+
+    pub fn approve(&mut self, spender: &Address, amount: &Balance) {
+        self.lq_token.approve(spender, amount);
+    }
+
+    // other token, reserve and weight of `token` followed by the reserve and weight of the other token
+    fn sides(&self, token: Address) -> (Address, Balance, u32, Balance, u32) {
+        let token0_address: Address = self.token0_address.get().unwrap();
+        let token1_address: Address = self.token1_address.get().unwrap();
+        let reserve0: Balance = self.reserve0.get().unwrap();
+        let reserve1: Balance = self.reserve1.get().unwrap();
+        let weight0: u32 = self.weight0.get().unwrap();
+        let weight1: u32 = self.weight1.get().unwrap();
+        if reserve0 == Balance::zero() || reserve1 == Balance::zero() {
+            contract_env::revert(Error::NotInitialized)
+        }
+        if token == token0_address {
+            (token1_address, reserve0, weight0, reserve1, weight1)
+        } else if token == token1_address {
+            (token0_address, reserve1, weight1, reserve0, weight0)
+        } else {
+            contract_env::revert(Error::InvalidToken)
+        }
+    }
+
+    // the fee is taken from the input
+    fn amount_out(&self, amount_in: Balance, token_in: Address) -> (Address, Balance) {
+        let (token_out, reserve_in, weight_in, reserve_out, weight_out) = self.sides(token_in);
+        if amount_in > reserve_in * Balance::from(MAX_IN_RATIO) / Balance::from(FEE_DENOMINATOR) {
+            contract_env::revert(Error::MaxInRatio)
+        }
+        let amount_in_after_fee: Balance = amount_in * Balance::from(FEE_DENOMINATOR - self.fee.get().unwrap()) / Balance::from(FEE_DENOMINATOR);
+        (token_out, _weighted_amount_out(reserve_in, weight_in, reserve_out, weight_out, amount_in_after_fee))
+    }
+
+    fn amount_in(&self, amount_out: Balance, token_out: Address) -> (Address, Balance) {
+        let (token_in, reserve_out, weight_out, reserve_in, weight_in) = self.sides(token_out);
+        if amount_out > reserve_out * Balance::from(MAX_OUT_RATIO) / Balance::from(FEE_DENOMINATOR) {
+            contract_env::revert(Error::MaxOutRatio)
+        }
+        let amount_in_after_fee: Balance = _weighted_amount_in(reserve_in, weight_in, reserve_out, weight_out, amount_out);
+        let fee_complement: Balance = Balance::from(FEE_DENOMINATOR - self.fee.get().unwrap());
+        (token_in, (amount_in_after_fee * Balance::from(FEE_DENOMINATOR) + fee_complement - Balance::from(1)) / fee_complement)
+    }
+
+    fn settle(&mut self, caller: Address, token_in: Address, amount_in: Balance, token_out: Address, amount_out: Balance) {
+        Erc20Ref::at(&token_in).transfer_from(&caller, &contract_env::self_address(), &amount_in);
+        Erc20Ref::at(&token_out).transfer(&caller, &amount_out);
+        self.update();
+        Swap {
+            sender: caller,
+            amount_in,
+            amount_out,
+            token_in,
+            to: caller
+        }
+        .emit();
+    }
+
+    // sets the reserves to the current token balances of the pool
+    fn update(&mut self) {
+        self.reserve0.set(Erc20Ref::at(&self.token0_address.get().unwrap()).balance_of(&contract_env::self_address()));
+        self.reserve1.set(Erc20Ref::at(&self.token1_address.get().unwrap()).balance_of(&contract_env::self_address()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use odra::test_env;
+    use odra::types::{Address, Balance, OdraError, VmError};
+    use crate::erc20::{Erc20Deployer, Erc20Ref};
+    use super::{Error, WeightedPoolDeployer, WeightedPoolRef};
+
+    fn deploy_token(symbol: &str) -> Address {
+        Erc20Deployer::init(symbol.to_string(), symbol.to_string(), 18u8, &Balance::from(0u128)).address().to_owned()
+    }
+
+    // 80/20 pool with a 0.3% fee, initialized by account 1 at a price of 1:1
+    fn setup() -> (Address, WeightedPoolRef) {
+        let user: Address = test_env::get_account(1);
+        let token0_address: Address = deploy_token("TKN0");
+        let token1_address: Address = deploy_token("TKN1");
+        let mut pool: WeightedPoolRef = WeightedPoolDeployer::init(token0_address, token1_address, 8000, 2000, 30, test_env::get_account(0));
+        Erc20Ref::at(&token0_address).mint(&user, &Balance::from(800_000u128));
+        Erc20Ref::at(&token1_address).mint(&user, &Balance::from(200_000u128));
+        test_env::set_caller(user);
+        Erc20Ref::at(&token0_address).approve(pool.address(), &Balance::from(800_000u128));
+        Erc20Ref::at(&token1_address).approve(pool.address(), &Balance::from(200_000u128));
+        pool.initialize(Balance::from(800_000u128), Balance::from(200_000u128));
+        test_env::set_caller(test_env::get_account(0));
+        (user, pool)
+    }
+
+    fn fund(pool: &WeightedPoolRef, user: Address, amount0: Balance, amount1: Balance) {
+        Erc20Ref::at(&pool.token0()).mint(&user, &amount0);
+        Erc20Ref::at(&pool.token1()).mint(&user, &amount1);
+        test_env::set_caller(user);
+        Erc20Ref::at(&pool.token0()).approve(pool.address(), &amount0);
+        Erc20Ref::at(&pool.token1()).approve(pool.address(), &amount1);
+    }
+
+    #[test]
+    fn initialize() {
+        let (user, mut pool) = setup();
+        assert_eq!(pool.balance_of(&user), Balance::from(399_000u128));
+        assert_eq!(pool.total_supply(), Balance::from(400_000u128));
+        assert_eq!(pool.weights(), (8000, 2000));
+        // shares are only burned by the pool itself
+        test_env::assert_exception(OdraError::VmError(VmError::NoSuchMethod("burn".to_string())), || {
+            Erc20Ref::at(pool.address()).burn(&user, &Balance::from(1_000u128));
+        });
+        test_env::assert_exception(Error::AlreadyInitialized, || {
+            pool.initialize(Balance::from(800_000u128), Balance::from(200_000u128));
+        });
+    }
+
+    #[test]
+    fn join_and_exit() {
+        let (user, mut pool) = setup();
+        fund(&pool, user, Balance::from(80_000u128), Balance::from(20_000u128));
+        test_env::assert_exception(Error::ExcessiveInputAmount, || {
+            pool.join_pool(Balance::from(40_000u128), Balance::from(80_000u128), Balance::from(19_999u128));
+        });
+        // 10% of the supply costs 10% of each reserve
+        let joined: (Balance, Balance) = pool.join_pool(Balance::from(40_000u128), Balance::from(80_000u128), Balance::from(20_000u128));
+        assert_eq!(joined, (Balance::from(80_000u128), Balance::from(20_000u128)));
+        assert_eq!(pool.balance_of(&user), Balance::from(439_000u128));
+        let exited: (Balance, Balance) = pool.exit_pool(Balance::from(40_000u128), Balance::zero(), Balance::zero(), u64::MAX);
+        assert_eq!(exited, (Balance::from(80_000u128), Balance::from(20_000u128)));
+        assert_eq!(pool.reserve0(), Balance::from(800_000u128));
+        assert_eq!(pool.reserve1(), Balance::from(200_000u128));
+    }
+
+    #[test]
+    fn swap_exact_in() {
+        let (user, mut pool) = setup();
+        let token1_address: Address = pool.token1();
+        fund(&pool, user, Balance::zero(), Balance::from(10_000u128));
+        let quoted: Balance = pool.get_amount_out(Balance::from(10_000u128), token1_address);
+        let amount_out: Balance = pool.swap_exact_in(Balance::from(10_000u128), token1_address, Balance::zero());
+        assert_eq!(amount_out, quoted);
+        assert_eq!(amount_out, Balance::from(9_670u128));
+        assert_eq!(Erc20Ref::at(&pool.token0()).balance_of(&user), amount_out);
+        assert_eq!(pool.reserve1(), Balance::from(210_000u128));
+    }
+
+    #[test]
+    fn swap_exact_out() {
+        let (user, mut pool) = setup();
+        let token0_address: Address = pool.token0();
+        fund(&pool, user, Balance::zero(), Balance::from(10_000u128));
+        test_env::assert_exception(Error::ExcessiveInputAmount, || {
+            pool.swap_exact_out(Balance::from(9_000u128), token0_address, Balance::from(9_286u128));
+        });
+        let amount_in: Balance = pool.swap_exact_out(Balance::from(9_000u128), token0_address, Balance::from(10_000u128));
+        assert_eq!(amount_in, Balance::from(9_287u128));
+        assert_eq!(Erc20Ref::at(&token0_address).balance_of(&user), Balance::from(9_000u128));
+        assert_eq!(pool.reserve1(), Balance::from(209_287u128));
+    }
+
+    #[test]
+    fn swap_errors() {
+        let (user, mut pool) = setup();
+        let token0_address: Address = pool.token0();
+        let token1_address: Address = pool.token1();
+        fund(&pool, user, Balance::zero(), Balance::from(100_000u128));
+        test_env::assert_exception(Error::MaxInRatio, || {
+            pool.swap_exact_in(Balance::from(60_001u128), token1_address, Balance::zero());
+        });
+        test_env::assert_exception(Error::MaxOutRatio, || {
+            pool.swap_exact_out(Balance::from(240_001u128), token0_address, Balance::from(100_000u128));
+        });
+        test_env::assert_exception(Error::InvalidToken, || {
+            pool.swap_exact_in(Balance::from(1_000u128), user, Balance::zero());
+        });
+    }
+
+    #[test]
+    fn invalid_weights() {
+        let token0: Address = deploy_token("TKN0");
+        let token1: Address = deploy_token("TKN1");
+        test_env::assert_exception(Error::IdenticalAddresses, || {
+            WeightedPoolDeployer::init(token0, token0, 8000, 2000, 30, test_env::get_account(0));
+        });
+        test_env::assert_exception(Error::InvalidWeights, || {
+            WeightedPoolDeployer::init(token0, token1, 8000, 1000, 30, test_env::get_account(0));
+        });
+        test_env::assert_exception(Error::InvalidWeights, || {
+            WeightedPoolDeployer::init(token0, token1, 9950, 50, 30, test_env::get_account(0));
+        });
+        // weights summing past u32::MAX are rejected instead of wrapping around
+        test_env::assert_exception(Error::InvalidWeights, || {
+            WeightedPoolDeployer::init(token0, token1, u32::MAX, 10001, 30, test_env::get_account(0));
+        });
+    }
+}
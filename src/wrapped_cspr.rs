@@ -0,0 +1,128 @@
+use odra::{contract_env, Event};
+use odra::types::{Address, Balance};
+use odra::types::event::OdraEvent;
+use crate::erc20::{Erc20, Transfer, Approval};
+
+// Erc20 backed 1:1 by the native CSPR held by the contract
+#[odra::module(events = [Deposit, Withdrawal, Transfer, Approval])]
+pub struct WrappedCspr {
+    erc20: Erc20
+}
+
+#[odra::module]
+impl WrappedCspr {
+    #[odra(init)]
+    pub fn init(&mut self) {
+        self.erc20.init_embedded("Wrapped CSPR".to_string(), "WCSPR".to_string(), 9u8);
+    }
+
+    // mints the attached CSPR to the caller
+    #[odra(payable)]
+    pub fn deposit(&mut self) {
+        let caller: Address = contract_env::caller();
+        let amount: Balance = contract_env::attached_value();
+        self.erc20.raw_mint(&caller, &amount);
+        Deposit {
+            account: caller,
+            amount
+        }
+        .emit();
+    }
+
+    pub fn withdraw(&mut self, amount: Balance) {
+        self.withdraw_to(contract_env::caller(), amount);
+    }
+
+    // burns `amount` of the caller and sends the CSPR to `recipient`
+    pub fn withdraw_to(&mut self, recipient: Address, amount: Balance) {
+        let caller: Address = contract_env::caller();
+        self.erc20.raw_burn(&caller, &amount);
+        contract_env::transfer_tokens(&recipient, amount);
+        Withdrawal {
+            account: caller,
+            recipient,
+            amount
+        }
+        .emit();
+    }
+
+    pub fn name(&self) -> String {
+        self.erc20.name()
+    }
+
+    pub fn symbol(&self) -> String {
+        self.erc20.symbol()
+    }
+
+    pub fn decimals(&self) -> u8 {
+        self.erc20.decimals()
+    }
+
+    pub fn total_supply(&self) -> Balance {
+        self.erc20.total_supply()
+    }
+
+    pub fn balance_of(&self, address: &Address) -> Balance {
+        self.erc20.balance_of(address)
+    }
+
+    pub fn allowance(&self, owner: &Address, spender: &Address) -> Balance {
+        self.erc20.allowance(owner, spender)
+    }
+
+    pub fn transfer(&mut self, recipient: &Address, amount: &Balance) {
+        self.erc20.transfer(recipient, amount);
+    }
+
+    pub fn transfer_from(&mut self, owner: &Address, recipient: &Address, amount: &Balance) {
+        self.erc20.transfer_from(owner, recipient, amount);
+    }
+
+    pub fn approve(&mut self, spender: &Address, amount: &Balance) {
+        self.erc20.approve(spender, amount);
+    }
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Deposit {
+    pub account: Address,
+    pub amount: Balance
+}
+
+#[derive(Event, PartialEq, Eq, Debug)]
+pub struct Withdrawal {
+    pub account: Address,
+    pub recipient: Address,
+    pub amount: Balance
+}
+
+#[cfg(test)]
+mod tests {
+    use odra::{assert_events, test_env};
+    use odra::types::{Address, Balance};
+    use crate::erc20::Error as Erc20Error;
+    use super::{Deposit, Withdrawal, WrappedCsprDeployer, WrappedCsprRef};
+
+    #[test]
+    fn deposit_and_withdraw() {
+        let user: Address = test_env::get_account(1);
+        let recipient: Address = test_env::get_account(2);
+        let mut wcspr: WrappedCsprRef = WrappedCsprDeployer::init();
+        test_env::set_caller(user);
+        wcspr.with_tokens(Balance::from(1_000u128)).deposit();
+        assert_eq!(wcspr.balance_of(&user), Balance::from(1_000u128));
+        assert_eq!(test_env::token_balance(*wcspr.address()), Balance::from(1_000u128));
+        assert_events!(wcspr, Deposit { account: user, amount: Balance::from(1_000u128) });
+        // withdrawing burns the wrapped tokens and releases the CSPR
+        let before: Balance = test_env::token_balance(recipient);
+        wcspr.withdraw_to(recipient, Balance::from(400u128));
+        assert_eq!(wcspr.balance_of(&user), Balance::from(600u128));
+        assert_eq!(wcspr.total_supply(), Balance::from(600u128));
+        assert_eq!(test_env::token_balance(recipient), before + Balance::from(400u128));
+        assert_eq!(test_env::token_balance(*wcspr.address()), Balance::from(600u128));
+        assert_events!(wcspr, Withdrawal { account: user, recipient, amount: Balance::from(400u128) });
+        test_env::assert_exception(Erc20Error::InsufficientBalance, || {
+            wcspr.withdraw(Balance::from(601u128));
+        });
+    }
+}